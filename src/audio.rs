@@ -0,0 +1,552 @@
+use libpulse_binding::{
+    callbacks::ListResult,
+    context::{
+        subscribe::{Facility, InterestMaskSet},
+        Context, FlagSet as ContextFlagSet, State as ContextState,
+    },
+    proplist::properties,
+    volume::{ChannelVolumes, Volume},
+};
+use libpulse_glib_binding::Mainloop;
+
+use gtk4::glib;
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+/// A sink or source with its current volume.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioDevice {
+    pub index:       u32,
+    pub name:        String,
+    pub description: String,
+    pub volume:      u32,
+    pub muted:       bool,
+    pub running:     bool,
+    pub is_default:  bool,
+}
+
+/// An application's playback stream (a sink-input).
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioStream {
+    pub index:    u32,
+    pub app_name: String,
+    pub title:    String,
+    pub icon:     Option<String>,
+    pub volume:   u32,
+    pub muted:    bool,
+    pub sink:     u32,
+}
+
+/// An application recording from a source (a source-output).
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioCapture {
+    pub index:    u32,
+    pub app_name: String,
+    pub source:   u32,
+}
+
+/// Everything the OSD and the control overlay show about audio, rebuilt from
+/// libpulse introspection whenever the server reports a change.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AudioState {
+    pub sinks:   Vec<AudioDevice>,
+    pub sources: Vec<AudioDevice>,
+    pub streams: Vec<AudioStream>,
+    pub captures: Vec<AudioCapture>,
+}
+
+impl AudioState {
+    pub fn default_sink(&self) -> Option<&AudioDevice> {
+        self.sinks.iter().find(|d| d.is_default)
+    }
+
+    pub fn default_source(&self) -> Option<&AudioDevice> {
+        self.sources.iter().find(|d| d.is_default)
+    }
+}
+
+pub type ListenerId = u64;
+type Listener = Rc<dyn Fn(&AudioState)>;
+
+struct AudioService {
+    context:   Rc<RefCell<Context>>,
+    state:     RefCell<Option<AudioState>>,
+    listeners: RefCell<Vec<(ListenerId, Listener)>>,
+    next_id:   Cell<ListenerId>,
+    queued:    Cell<bool>,
+    /// Relative steps that arrived while one was in flight; `None` when idle.
+    sink_step:   Cell<Option<i32>>,
+    source_step: Cell<Option<i32>>,
+    _mainloop: Mainloop,
+}
+
+thread_local! {
+    static SERVICE: RefCell<Option<Rc<AudioService>>> = const { RefCell::new(None) };
+}
+
+fn service() -> Option<Rc<AudioService>> {
+    SERVICE.with(|s| s.borrow().clone())
+}
+
+fn ready_context() -> Option<Rc<RefCell<Context>>> {
+    let svc = service()?;
+    let ready = svc.context.borrow().get_state() == ContextState::Ready;
+    ready.then(|| Rc::clone(&svc.context))
+}
+
+// ─── service ──────────────────────────────────────────────────────────────────
+
+/// Connects the shared libpulse context on the glib main loop. Safe to call
+/// more than once; later calls are no-ops.
+pub fn start() {
+    if service().is_some() { return; }
+
+    let mainloop = Mainloop::new(None).expect("PA glib mainloop");
+    let context  = Rc::new(RefCell::new(
+        Context::new(&mainloop, "capsule").expect("PA context"),
+    ));
+
+    let svc = Rc::new(AudioService {
+        context:   Rc::clone(&context),
+        state:     RefCell::new(None),
+        listeners: RefCell::new(Vec::new()),
+        next_id:   Cell::new(0),
+        queued:    Cell::new(false),
+        sink_step:   Cell::new(None),
+        source_step: Cell::new(None),
+        _mainloop: mainloop,
+    });
+    SERVICE.with(|s| *s.borrow_mut() = Some(Rc::clone(&svc)));
+
+    {
+        let ctx = Rc::downgrade(&context);
+        context.borrow_mut().set_state_callback(Some(Box::new(move || {
+            let Some(ctx) = ctx.upgrade() else { return };
+            let cs = unsafe { (*ctx.as_ptr()).get_state() };
+            match cs {
+                ContextState::Ready => {
+                    glib::idle_add_local_once(on_context_ready);
+                }
+                ContextState::Failed | ContextState::Terminated => {
+                    eprintln!("[audio] PA context failed/terminated");
+                }
+                _ => {}
+            }
+        })));
+    }
+
+    context
+        .borrow_mut()
+        .connect(None, ContextFlagSet::NOFLAGS, None)
+        .expect("PA connect");
+}
+
+fn on_context_ready() {
+    let Some(ctx) = ready_context() else { return };
+
+    ctx.borrow_mut().subscribe(
+        InterestMaskSet::SINK
+            | InterestMaskSet::SOURCE
+            | InterestMaskSet::SINK_INPUT
+            | InterestMaskSet::SOURCE_OUTPUT
+            | InterestMaskSet::SERVER,
+        |_| {},
+    );
+    ctx.borrow_mut().set_subscribe_callback(Some(Box::new(|facility, _op, _index| {
+        if matches!(
+            facility,
+            Some(Facility::Sink)
+                | Some(Facility::Source)
+                | Some(Facility::SinkInput)
+                | Some(Facility::SourceOutput)
+                | Some(Facility::Server)
+        ) {
+            queue_refresh();
+        }
+    })));
+
+    queue_refresh();
+}
+
+/// Coalesces bursts of subscription events into a single introspection pass.
+fn queue_refresh() {
+    let Some(svc) = service() else { return };
+    if svc.queued.replace(true) { return; }
+    glib::idle_add_local_once(move || {
+        svc.queued.set(false);
+        refresh();
+    });
+}
+
+fn device_volume(volume: &ChannelVolumes) -> u32 {
+    pa_vol_to_percent(volume.avg())
+}
+
+type Pending = Rc<RefCell<AudioState>>;
+
+/// One pass: server defaults → sinks → sources → streams → captures, then publish.
+fn refresh() {
+    let Some(ctx) = ready_context() else { return };
+
+    let ctx2 = Rc::clone(&ctx);
+    let _ = ctx.borrow().introspect().get_server_info(move |server| {
+        let default_sink   = server.default_sink_name.as_deref().unwrap_or("").to_string();
+        let default_source = server.default_source_name.as_deref().unwrap_or("").to_string();
+        fetch_sinks(&ctx2, Pending::default(), default_sink, default_source);
+    });
+}
+
+fn fetch_sinks(ctx: &Rc<RefCell<Context>>, pending: Pending, default_sink: String, default_source: String) {
+    let ctx2 = Rc::clone(ctx);
+    let _ = ctx.borrow().introspect().get_sink_info_list(move |res| match res {
+        ListResult::Item(info) => {
+            let name = info.name.as_deref().unwrap_or("").to_string();
+            pending.borrow_mut().sinks.push(AudioDevice {
+                index:       info.index,
+                is_default:  name == default_sink,
+                description: info.description.as_deref().unwrap_or(&name).to_string(),
+                volume:      device_volume(&info.volume),
+                muted:       info.mute,
+                running:     matches!(info.state, libpulse_binding::def::SinkState::Running),
+                name,
+            });
+        }
+        ListResult::End | ListResult::Error => {
+            fetch_sources(&ctx2, Rc::clone(&pending), default_source.clone());
+        }
+    });
+}
+
+fn fetch_sources(ctx: &Rc<RefCell<Context>>, pending: Pending, default_source: String) {
+    let ctx2 = Rc::clone(ctx);
+    let _ = ctx.borrow().introspect().get_source_info_list(move |res| match res {
+        ListResult::Item(info) => {
+            if info.monitor_of_sink.is_some() { return; }
+            let name = info.name.as_deref().unwrap_or("").to_string();
+            pending.borrow_mut().sources.push(AudioDevice {
+                index:       info.index,
+                is_default:  name == default_source,
+                description: info.description.as_deref().unwrap_or(&name).to_string(),
+                volume:      device_volume(&info.volume),
+                muted:       info.mute,
+                running:     matches!(info.state, libpulse_binding::def::SourceState::Running),
+                name,
+            });
+        }
+        ListResult::End | ListResult::Error => {
+            fetch_streams(&ctx2, Rc::clone(&pending));
+        }
+    });
+}
+
+fn fetch_streams(ctx: &Rc<RefCell<Context>>, pending: Pending) {
+    let ctx2 = Rc::clone(ctx);
+    let _ = ctx.borrow().introspect().get_sink_input_info_list(move |res| match res {
+        ListResult::Item(info) => {
+            if !info.has_volume { return; }
+            let props    = &info.proplist;
+            let app_name = props.get_str(properties::APPLICATION_NAME)
+                .or_else(|| props.get_str(properties::APPLICATION_PROCESS_BINARY))
+                .unwrap_or_else(|| info.name.as_deref().unwrap_or("Unknown").to_string());
+            pending.borrow_mut().streams.push(AudioStream {
+                index:  info.index,
+                title:  props.get_str(properties::MEDIA_NAME).unwrap_or_default(),
+                icon:   props.get_str(properties::APPLICATION_ICON_NAME),
+                volume: device_volume(&info.volume),
+                muted:  info.mute,
+                sink:   info.sink,
+                app_name,
+            });
+        }
+        ListResult::End | ListResult::Error => {
+            fetch_captures(&ctx2, Rc::clone(&pending));
+        }
+    });
+}
+
+fn fetch_captures(ctx: &Rc<RefCell<Context>>, pending: Pending) {
+    let _ = ctx.borrow().introspect().get_source_output_info_list(move |res| match res {
+        ListResult::Item(info) => {
+            // Only real inputs count; peak meters on sink monitors aren't recording anyone.
+            if !pending.borrow().sources.iter().any(|s| s.index == info.source) { return; }
+            let props    = &info.proplist;
+            let app_name = props.get_str(properties::APPLICATION_NAME)
+                .or_else(|| props.get_str(properties::APPLICATION_PROCESS_BINARY))
+                .unwrap_or_else(|| info.name.as_deref().unwrap_or("Unknown").to_string());
+            pending.borrow_mut().captures.push(AudioCapture {
+                index:  info.index,
+                source: info.source,
+                app_name,
+            });
+        }
+        ListResult::End | ListResult::Error => {
+            publish(pending.take());
+        }
+    });
+}
+
+fn publish(state: AudioState) {
+    let Some(svc) = service() else { return };
+    if svc.state.borrow().as_ref() == Some(&state) { return; }
+    *svc.state.borrow_mut() = Some(state.clone());
+
+    let listeners: Vec<_> = svc.listeners.borrow().iter().map(|(_, f)| Rc::clone(f)).collect();
+    for listener in listeners {
+        listener(&state);
+    }
+}
+
+/// Calls `listener` with every new state, and right away if one is known.
+pub fn subscribe(listener: impl Fn(&AudioState) + 'static) -> Option<ListenerId> {
+    let svc = service()?;
+    let id  = svc.next_id.get();
+    svc.next_id.set(id + 1);
+
+    let listener: Listener = Rc::new(listener);
+    svc.listeners.borrow_mut().push((id, Rc::clone(&listener)));
+
+    let current = svc.state.borrow().clone();
+    if let Some(state) = current {
+        listener(&state);
+    }
+    Some(id)
+}
+
+pub fn unsubscribe(id: ListenerId) {
+    let Some(svc) = service() else { return };
+    svc.listeners.borrow_mut().retain(|(lid, _)| *lid != id);
+}
+
+// ─── device control ───────────────────────────────────────────────────────────
+
+pub fn set_default_sink(name: &str) {
+    let Some(ctx) = ready_context() else { return };
+    let _ = ctx.borrow_mut().set_default_sink(name, |_| {});
+}
+
+pub fn set_default_source(name: &str) {
+    let Some(ctx) = ready_context() else { return };
+    let _ = ctx.borrow_mut().set_default_source(name, |_| {});
+}
+
+pub fn set_sink_mute(name: &str, muted: bool) {
+    let Some(ctx) = ready_context() else { return };
+    let _ = ctx.borrow().introspect().set_sink_mute_by_name(name, muted, None);
+}
+
+pub fn set_source_mute(name: &str, muted: bool) {
+    let Some(ctx) = ready_context() else { return };
+    let _ = ctx.borrow().introspect().set_source_mute_by_name(name, muted, None);
+}
+
+pub fn set_sink_volume(name: &str, percent: u32) {
+    let Some(ctx) = ready_context() else { return };
+    let ctx2   = Rc::clone(&ctx);
+    let target = name.to_string();
+    let _ = ctx.borrow().introspect().get_sink_info_by_name(name, move |res| {
+        let ListResult::Item(info) = res else { return };
+        let volume = scaled_volume(info.volume, percent);
+        let _ = ctx2.borrow().introspect().set_sink_volume_by_name(&target, &volume, None);
+    });
+}
+
+pub fn set_source_volume(name: &str, percent: u32) {
+    let Some(ctx) = ready_context() else { return };
+    let ctx2   = Rc::clone(&ctx);
+    let target = name.to_string();
+    let _ = ctx.borrow().introspect().get_source_info_by_name(name, move |res| {
+        let ListResult::Item(info) = res else { return };
+        let volume = scaled_volume(info.volume, percent);
+        let _ = ctx2.borrow().introspect().set_source_volume_by_name(&target, &volume, None);
+    });
+}
+
+pub fn set_stream_volume(index: u32, percent: u32) {
+    let Some(ctx) = ready_context() else { return };
+    let ctx2 = Rc::clone(&ctx);
+    let _ = ctx.borrow().introspect().get_sink_input_info(index, move |res| {
+        let ListResult::Item(info) = res else { return };
+        let volume = scaled_volume(info.volume, percent);
+        let _ = ctx2.borrow().introspect().set_sink_input_volume(index, &volume, None);
+    });
+}
+
+pub fn set_stream_mute(index: u32, muted: bool) {
+    let Some(ctx) = ready_context() else { return };
+    let _ = ctx.borrow().introspect().set_sink_input_mute(index, muted, None);
+}
+
+pub fn move_stream(index: u32, sink_name: &str) {
+    let Some(ctx) = ready_context() else { return };
+    let _ = ctx.borrow().introspect().move_sink_input_by_name(index, sink_name, None);
+}
+
+pub fn toggle_source_mute() {
+    let Some(svc) = service() else { return };
+    let Some(source) = svc.state.borrow().as_ref().and_then(|s| s.default_source().cloned()) else { return };
+    set_source_mute(&source.name, !source.muted);
+}
+
+/// Steps the default sink by `delta` percent relative to its live volume,
+/// keeping channel balance. Raising stops at 100% unless already above it.
+pub fn adjust_sink_volume(delta: i32) {
+    let Some(svc) = service() else { return };
+    if queue_step(&svc.sink_step, delta) {
+        step_sink(delta);
+    }
+}
+
+pub fn adjust_source_volume(delta: i32) {
+    let Some(svc) = service() else { return };
+    if queue_step(&svc.source_step, delta) {
+        step_source(delta);
+    }
+}
+
+/// Returns true when no step is in flight and the caller should send this one;
+/// otherwise folds `delta` into the step sent once the current one lands.
+fn queue_step(pending: &Cell<Option<i32>>, delta: i32) -> bool {
+    match pending.get() {
+        Some(acc) => { pending.set(Some(acc + delta)); false }
+        None      => { pending.set(Some(0)); true }
+    }
+}
+
+/// Once a step lands, takes the steps queued meanwhile as the next one in
+/// flight, or clears the cell when nothing is queued.
+fn take_queued(pending: &Cell<Option<i32>>) -> Option<i32> {
+    match pending.get() {
+        Some(acc) if acc != 0 => { pending.set(Some(0)); Some(acc) }
+        _ => { pending.set(None); None }
+    }
+}
+
+fn finish_step(pending: fn(&AudioService) -> &Cell<Option<i32>>, next: fn(i32)) {
+    let Some(svc) = service() else { return };
+    if let Some(acc) = take_queued(pending(&svc)) {
+        next(acc);
+    }
+}
+
+/// Drops the step in flight and anything queued behind it, so later steps go out.
+fn cancel_step(pending: fn(&AudioService) -> &Cell<Option<i32>>) {
+    if let Some(svc) = service() { pending(&svc).set(None); }
+}
+
+fn step_sink(delta: i32) {
+    let Some(ctx) = ready_context() else { return cancel_step(|s| &s.sink_step) };
+    let ctx2 = Rc::clone(&ctx);
+    let _ = ctx.borrow().introspect().get_sink_info_by_name("@DEFAULT_SINK@", move |res| match res {
+        ListResult::Item(info) => {
+            let volume = stepped_volume(info.volume, delta);
+            let done = Box::new(|_| finish_step(|s| &s.sink_step, step_sink));
+            let _ = ctx2.borrow().introspect().set_sink_volume_by_index(info.index, &volume, Some(done));
+        }
+        ListResult::Error => cancel_step(|s| &s.sink_step),
+        ListResult::End => {}
+    });
+}
+
+fn step_source(delta: i32) {
+    let Some(ctx) = ready_context() else { return cancel_step(|s| &s.source_step) };
+    let ctx2 = Rc::clone(&ctx);
+    let _ = ctx.borrow().introspect().get_source_info_by_name("@DEFAULT_SOURCE@", move |res| match res {
+        ListResult::Item(info) => {
+            let volume = stepped_volume(info.volume, delta);
+            let done = Box::new(|_| finish_step(|s| &s.source_step, step_source));
+            let _ = ctx2.borrow().introspect().set_source_volume_by_index(info.index, &volume, Some(done));
+        }
+        ListResult::Error => cancel_step(|s| &s.source_step),
+        ListResult::End => {}
+    });
+}
+
+// ─── helpers ──────────────────────────────────────────────────────────────────
+
+fn pa_vol_to_percent(v: Volume) -> u32 {
+    let norm = Volume::NORMAL.0 as f64;
+    ((v.0 as f64 / norm) * 100.0).round().clamp(0.0, 150.0) as u32
+}
+
+fn percent_to_pa_vol(percent: u32) -> Volume {
+    let norm = Volume::NORMAL.0 as f64;
+    Volume(((percent.min(150) as f64 / 100.0) * norm).round() as u32)
+}
+
+/// Sets the loudest channel to `percent`, scaling the others to keep balance.
+fn scaled_volume(mut current: ChannelVolumes, percent: u32) -> ChannelVolumes {
+    current.scale(percent_to_pa_vol(percent));
+    current
+}
+
+fn stepped_volume(mut current: ChannelVolumes, delta: i32) -> ChannelVolumes {
+    let step = percent_to_pa_vol(delta.unsigned_abs());
+    if delta >= 0 {
+        let limit = current.max().max(Volume::NORMAL);
+        current.inc_clamp(step, limit);
+    } else {
+        current.decrease(step);
+    }
+    current
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn volumes(levels: &[u32]) -> ChannelVolumes {
+        let mut cv = ChannelVolumes::default();
+        cv.set(levels.len() as u8, Volume::MUTED);
+        for (channel, level) in cv.get_mut().iter_mut().zip(levels) {
+            *channel = percent_to_pa_vol(*level);
+        }
+        cv
+    }
+
+    fn percents(cv: &ChannelVolumes) -> Vec<u32> {
+        cv.get().iter().map(|v| pa_vol_to_percent(*v)).collect()
+    }
+
+    #[test]
+    fn steps_queue_behind_the_one_in_flight() {
+        let pending = Cell::new(None);
+        assert!(queue_step(&pending, 5));
+        assert!(!queue_step(&pending, 5));
+        assert!(!queue_step(&pending, -2));
+        assert_eq!(pending.get(), Some(3));
+
+        assert_eq!(take_queued(&pending), Some(3));
+        assert_eq!(pending.get(), Some(0));
+        assert!(!queue_step(&pending, 1));
+
+        assert_eq!(take_queued(&pending), Some(1));
+        assert_eq!(take_queued(&pending), None);
+        assert_eq!(pending.get(), None);
+        assert!(queue_step(&pending, 5));
+    }
+
+    #[test]
+    fn steps_that_cancel_out_end_the_chain() {
+        let pending = Cell::new(None);
+        assert!(queue_step(&pending, 5));
+        assert!(!queue_step(&pending, 5));
+        assert!(!queue_step(&pending, -5));
+        assert_eq!(take_queued(&pending), None);
+        assert_eq!(pending.get(), None);
+    }
+
+    #[test]
+    fn stepping_keeps_channel_balance() {
+        assert_eq!(percents(&stepped_volume(volumes(&[40, 20]), 10)), [50, 25]);
+        assert_eq!(percents(&stepped_volume(volumes(&[40, 20]), -10)), [30, 15]);
+        assert_eq!(percents(&stepped_volume(volumes(&[10, 5]), -20)), [0, 0]);
+    }
+
+    #[test]
+    fn stepping_up_stops_at_100_unless_already_above() {
+        assert_eq!(percents(&stepped_volume(volumes(&[95, 95]), 10)), [100, 100]);
+        assert_eq!(percents(&stepped_volume(volumes(&[120, 120]), 10)), [120, 120]);
+        assert_eq!(percents(&stepped_volume(volumes(&[120, 120]), -10)), [110, 110]);
+    }
+}
@@ -0,0 +1,553 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::sync::mpsc as std_mpsc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc::{self, UnboundedSender};
+
+const SYSFS_BACKLIGHT: &str = "/sys/class/backlight";
+const SYSFS_DRM:       &str = "/sys/class/drm";
+
+/// Exponent of the perceptual curve; raw = max * (percent / 100) ^ GAMMA.
+const GAMMA: f64 = 2.0;
+
+/// Lowest perceptual percent a step can reach, so the panel never goes fully black.
+const MIN_PERCENT: u32 = 5;
+
+#[derive(Debug, Clone)]
+pub struct Backlight {
+    pub name:      String,
+    pub dir:       PathBuf,
+    pub max:       u64,
+    pub connector: Option<String>,
+}
+
+impl Backlight {
+    fn open(dir: PathBuf) -> Option<Self> {
+        let name = dir.file_name()?.to_string_lossy().into_owned();
+        let max: u64 = read_u64(&dir.join("max_brightness"))?;
+        if max == 0 || !dir.join("brightness").exists() {
+            return None;
+        }
+        let connector = connector_for(&dir);
+        Some(Self { name, dir, max, connector })
+    }
+
+    pub fn brightness_path(&self) -> PathBuf {
+        self.dir.join("brightness")
+    }
+
+    pub fn raw(&self) -> Option<u64> {
+        read_u64(&self.brightness_path())
+    }
+
+    pub fn percent(&self) -> Option<u32> {
+        Some(raw_to_percent(self.raw()?, self.max))
+    }
+
+    /// Kernel docs rank firmware over platform over raw interfaces.
+    fn priority(&self) -> u8 {
+        match std::fs::read_to_string(self.dir.join("type")).as_deref().map(str::trim) {
+            Ok("firmware") => 0,
+            Ok("platform") => 1,
+            _              => 2,
+        }
+    }
+}
+
+fn read_u64(path: &Path) -> Option<u64> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+pub fn raw_to_percent(raw: u64, max: u64) -> u32 {
+    let linear = raw.min(max) as f64 / max.max(1) as f64;
+    (linear.powf(1.0 / GAMMA) * 100.0).round() as u32
+}
+
+pub fn percent_to_raw(percent: u32, max: u64) -> u64 {
+    let perceptual = percent.min(100) as f64 / 100.0;
+    ((perceptual.powf(GAMMA) * max as f64).round() as u64).max(1)
+}
+
+/// Computes the raw value one step of `delta` perceptual percent away from `raw`,
+/// always moving at least one raw unit so low levels don't get stuck.
+fn step_raw(raw: u64, max: u64, delta: i32) -> u64 {
+    let raw     = raw.min(max);
+    let current = raw_to_percent(raw, max) as i32;
+    let target  = (current + delta).clamp(MIN_PERCENT as i32, 100) as u32;
+    let floor   = percent_to_raw(MIN_PERCENT, max);
+    let mut next = percent_to_raw(target, max);
+    if next == raw {
+        next = if delta > 0 { raw + 1 } else { raw.saturating_sub(1) };
+    }
+    let next = next.clamp(floor, max);
+    // A panel already below the floor stays put rather than brightening on a step down.
+    if delta < 0 { next.min(raw) } else { next.max(raw) }
+}
+
+// ─── drm connectors ───────────────────────────────────────────────────────────
+
+/// `card1-eDP-1` → `eDP-1`, the name gdk reports as the monitor connector.
+fn connector_name(entry: &str) -> Option<String> {
+    let (_, connector) = entry.strip_prefix("card")?.split_once('-')?;
+    Some(connector.to_string())
+}
+
+fn is_internal(connector: &str) -> bool {
+    ["eDP", "LVDS", "DSI"].iter().any(|p| connector.starts_with(p))
+}
+
+/// Connected drm connectors as `(connector, sysfs dir)`.
+fn drm_connectors() -> Vec<(String, PathBuf)> {
+    let Ok(dir) = std::fs::read_dir(SYSFS_DRM) else { return Vec::new() };
+    let mut connectors: Vec<(String, PathBuf)> = dir
+        .flatten()
+        .filter_map(|e| {
+            let name = connector_name(&e.file_name().to_string_lossy())?;
+            let status = std::fs::read_to_string(e.path().join("status")).ok()?;
+            (status.trim() == "connected").then(|| (name, e.path()))
+        })
+        .collect();
+    connectors.sort();
+    connectors
+}
+
+fn connector_for(dir: &Path) -> Option<String> {
+    // i915 and nouveau parent the backlight on the connector itself.
+    if let Ok(device) = std::fs::canonicalize(dir.join("device"))
+        && let Some(name) = device.file_name().and_then(|n| connector_name(&n.to_string_lossy()))
+    {
+        return Some(name);
+    }
+    // amdgpu and firmware backlights hang off the GPU; they drive the internal panel.
+    drm_connectors().into_iter().map(|(name, _)| name).find(|name| is_internal(name))
+}
+
+// ─── enumeration ──────────────────────────────────────────────────────────────
+
+pub fn devices_in(root: &Path) -> Vec<Backlight> {
+    let Ok(dir) = std::fs::read_dir(root) else { return Vec::new() };
+    let mut devices: Vec<Backlight> = dir
+        .flatten()
+        .filter_map(|e| Backlight::open(e.path()))
+        .collect();
+    devices.sort_by(|a, b| a.priority().cmp(&b.priority()).then_with(|| a.name.cmp(&b.name)));
+    devices
+}
+
+pub fn devices() -> Vec<Backlight> {
+    devices_in(Path::new(SYSFS_BACKLIGHT))
+}
+
+pub fn default_device() -> Option<Backlight> {
+    devices().into_iter().next()
+}
+
+/// External monitor reachable over DDC/CI on `/dev/i2c-<bus>`.
+#[derive(Debug, Clone)]
+pub struct DdcDisplay {
+    pub connector: String,
+    pub bus:       u32,
+}
+
+fn ddc_bus(dir: &Path) -> Option<u32> {
+    let bus_number = |path: &Path| -> Option<u32> {
+        path.file_name()?.to_str()?.strip_prefix("i2c-")?.parse().ok()
+    };
+    if let Ok(ddc) = std::fs::canonicalize(dir.join("ddc"))
+        && let Some(bus) = bus_number(&ddc)
+    {
+        return Some(bus);
+    }
+    // DisplayPort exposes the AUX channel's i2c adapter as a child instead.
+    std::fs::read_dir(dir).ok()?.flatten().find_map(|e| bus_number(&e.path()))
+}
+
+pub fn ddc_displays() -> Vec<DdcDisplay> {
+    drm_connectors()
+        .into_iter()
+        .filter(|(name, _)| !is_internal(name))
+        .filter_map(|(connector, dir)| Some(DdcDisplay { bus: ddc_bus(&dir)?, connector }))
+        .collect()
+}
+
+enum Device {
+    Sysfs(Backlight),
+    Ddc(DdcDisplay),
+}
+
+/// Resolves a sysfs backlight name or an output connector; `None` picks the
+/// preferred backlight, falling back to the first DDC monitor on desktops.
+fn resolve(name: Option<&str>) -> Option<Device> {
+    let Some(name) = name else {
+        return default_device()
+            .map(Device::Sysfs)
+            .or_else(|| ddc_displays().into_iter().next().map(Device::Ddc));
+    };
+    let sysfs = devices();
+    if let Some(dev) = sysfs.iter().find(|d| d.name == name || d.connector.as_deref() == Some(name)) {
+        return Some(Device::Sysfs(dev.clone()));
+    }
+    ddc_displays().into_iter().find(|d| d.connector == name).map(Device::Ddc)
+}
+
+/// Every brightness-controllable output: sysfs backlights by device name, then
+/// DDC monitors by connector.
+pub fn outputs() -> Vec<String> {
+    let mut names: Vec<String> = devices().into_iter().map(|d| d.name).collect();
+    names.extend(ddc_displays().into_iter().map(|d| d.connector));
+    names
+}
+
+// ─── DDC/CI ───────────────────────────────────────────────────────────────────
+
+const I2C_SLAVE:      u64 = 0x0703;
+const DDC_ADDR:       u64 = 0x37;
+const DDC_HOST:       u8  = 0x51;
+const DDC_DEST:       u8  = 0x6E;
+/// Replies are checksummed against the host's read address.
+const DDC_REPLY_HOST: u8  = 0x50;
+const VCP_BRIGHTNESS: u8  = 0x10;
+
+/// How long a read DDC level is trusted before the monitor is asked again,
+/// in case it was changed with the monitor's own buttons.
+const DDC_LEVEL_TTL: Duration = Duration::from_secs(3);
+
+fn ddc_open(bus: u32) -> std::io::Result<File> {
+    let file = OpenOptions::new().read(true).write(true).open(format!("/dev/i2c-{bus}"))?;
+    // SAFETY: I2C_SLAVE takes the 7-bit address by value and only touches the fd.
+    if unsafe { libc::ioctl(file.as_raw_fd(), I2C_SLAVE as _, DDC_ADDR) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(file)
+}
+
+fn ddc_write(file: &mut File, payload: &[u8]) -> std::io::Result<()> {
+    let mut packet = vec![DDC_HOST, 0x80 | payload.len() as u8];
+    packet.extend_from_slice(payload);
+    let checksum = packet.iter().fold(DDC_DEST, |acc, b| acc ^ b);
+    packet.push(checksum);
+    file.write_all(&packet)
+}
+
+/// Reads `(current, max)` of the brightness VCP feature.
+fn ddc_get_brightness(bus: u32) -> std::io::Result<(u16, u16)> {
+    let mut file = ddc_open(bus)?;
+    ddc_write(&mut file, &[0x01, VCP_BRIGHTNESS])?;
+    std::thread::sleep(Duration::from_millis(40));
+
+    let mut reply = [0u8; 11];
+    file.read_exact(&mut reply)?;
+    let checksum = reply[..10].iter().fold(DDC_REPLY_HOST, |acc, b| acc ^ b);
+    if checksum != reply[10] {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "bad VCP reply checksum"));
+    }
+    if reply[2] != 0x02 || reply[3] != 0x00 || reply[4] != VCP_BRIGHTNESS {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "unexpected VCP reply"));
+    }
+    let max = u16::from_be_bytes([reply[6], reply[7]]);
+    let cur = u16::from_be_bytes([reply[8], reply[9]]);
+    Ok((cur, max.max(1)))
+}
+
+fn ddc_set_brightness(bus: u32, value: u16) -> std::io::Result<()> {
+    let mut file = ddc_open(bus)?;
+    let [hi, lo] = value.to_be_bytes();
+    ddc_write(&mut file, &[0x03, VCP_BRIGHTNESS, hi, lo])?;
+    // Monitors ignore commands that arrive within 50 ms of the last one.
+    std::thread::sleep(Duration::from_millis(50));
+    Ok(())
+}
+
+// ─── change reports ───────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub connector: Option<String>,
+    pub percent:   u32,
+}
+
+static CHANGES: OnceLock<std_mpsc::Sender<Change>> = OnceLock::new();
+
+fn report(change: Change) {
+    if let Some(tx) = CHANGES.get() {
+        let _ = tx.send(change);
+    }
+}
+
+/// Follows every sysfs backlight with inotify. DDC monitors can't be watched,
+/// so the writer reports its own changes on the same channel.
+pub fn watch() -> Option<std_mpsc::Receiver<Change>> {
+    let (tx, rx) = std_mpsc::channel::<Change>();
+    CHANGES.set(tx.clone()).ok()?;
+
+    let devices = devices();
+    if devices.is_empty() {
+        return Some(rx);
+    }
+
+    std::thread::spawn(move || {
+        use inotify::{Inotify, WatchMask};
+
+        let mut inotify = match Inotify::init() {
+            Ok(i) => i,
+            Err(e) => { eprintln!("[backlight] inotify init failed: {e}"); return; }
+        };
+
+        let mut watches = HashMap::new();
+        for (i, dev) in devices.iter().enumerate() {
+            let path = dev.brightness_path();
+            match inotify.watches().add(&path, WatchMask::CLOSE_WRITE | WatchMask::MODIFY) {
+                Ok(wd) => { watches.insert(wd, i); }
+                Err(e) => eprintln!("[backlight] inotify watch failed on {}: {e}", path.display()),
+            }
+        }
+
+        let mut last: Vec<Option<u32>> = devices.iter().map(|d| d.percent()).collect();
+        let mut buf = [0u8; 1024];
+
+        loop {
+            let events = match inotify.read_events_blocking(&mut buf) {
+                Ok(events) => events,
+                Err(e) => {
+                    eprintln!("[backlight] inotify read error: {e}");
+                    break;
+                }
+            };
+            for event in events {
+                let Some(&i) = watches.get(&event.wd) else { continue };
+                let Some(percent) = devices[i].percent() else { continue };
+                if last[i] != Some(percent) {
+                    last[i] = Some(percent);
+                    if tx.send(Change { connector: devices[i].connector.clone(), percent }).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+
+    Some(rx)
+}
+
+// ─── logind writer ────────────────────────────────────────────────────────────
+
+struct Adjust {
+    device: Option<String>,
+    delta:  i32,
+}
+
+static WRITER: OnceLock<UnboundedSender<Adjust>> = OnceLock::new();
+
+async fn logind_set_brightness(conn: &zbus::Connection, name: &str, value: u32) -> zbus::Result<()> {
+    conn.call_method(
+        Some("org.freedesktop.login1"),
+        "/org/freedesktop/login1/session/auto",
+        Some("org.freedesktop.login1.Session"),
+        "SetBrightness",
+        &("backlight", name, value),
+    )
+    .await
+    .map(|_| ())
+}
+
+async fn set_sysfs(conn: &mut Option<zbus::Connection>, dev: &Backlight, delta: i32) {
+    let Some(raw) = dev.raw() else { return };
+    let next = step_raw(raw, dev.max, delta);
+    if next == raw {
+        return;
+    }
+
+    if conn.is_none() {
+        match zbus::Connection::system().await {
+            Ok(c) => *conn = Some(c),
+            Err(e) => eprintln!("[backlight] no system bus: {e}"),
+        }
+    }
+
+    let via_logind = match conn.as_ref() {
+        Some(c) => match logind_set_brightness(c, &dev.name, next as u32).await {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("[backlight] SetBrightness failed for {}: {e}", dev.name);
+                false
+            }
+        },
+        None => false,
+    };
+
+    if !via_logind {
+        let _ = std::fs::write(dev.brightness_path(), next.to_string());
+    }
+}
+
+struct DdcLevel {
+    cur: u16,
+    max: u16,
+    at:  Instant,
+}
+
+/// DDC monitors apply their own curve, so steps are linear in the VCP range.
+fn set_ddc(levels: &mut HashMap<u32, DdcLevel>, display: &DdcDisplay, delta: i32) {
+    let cached = levels
+        .get(&display.bus)
+        .filter(|level| level.at.elapsed() < DDC_LEVEL_TTL)
+        .map(|level| (level.cur, level.max));
+    let (cur, max) = match cached {
+        Some(level) => level,
+        None => match ddc_get_brightness(display.bus) {
+            Ok(level) => level,
+            Err(e) => {
+                eprintln!("[backlight] DDC read failed on {} (i2c-{}): {e}", display.connector, display.bus);
+                return;
+            }
+        },
+    };
+
+    let step = match delta * max as i32 / 100 {
+        0    => delta.signum(),
+        step => step,
+    };
+    let next = (cur as i32 + step).clamp(0, max as i32) as u16;
+    if next == cur {
+        return;
+    }
+
+    if let Err(e) = ddc_set_brightness(display.bus, next) {
+        eprintln!("[backlight] DDC write failed on {} (i2c-{}): {e}", display.connector, display.bus);
+        levels.remove(&display.bus);
+        return;
+    }
+    levels.insert(display.bus, DdcLevel { cur: next, max, at: Instant::now() });
+    report(Change {
+        connector: Some(display.connector.clone()),
+        percent:   (next as u32 * 100 / max as u32).min(100),
+    });
+}
+
+fn writer() -> &'static UnboundedSender<Adjust> {
+    WRITER.get_or_init(|| {
+        let (tx, mut rx) = mpsc::unbounded_channel::<Adjust>();
+        std::thread::spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(rt) => rt,
+                Err(e) => {
+                    eprintln!("[backlight] failed to start runtime: {e}");
+                    return;
+                }
+            };
+            runtime.block_on(async move {
+                let mut conn: Option<zbus::Connection> = None;
+                let mut ddc_levels: HashMap<u32, DdcLevel> = HashMap::new();
+
+                // Requests are handled one at a time, so sysfs already reflects the
+                // previous write when the next step reads the current level.
+                while let Some(req) = rx.recv().await {
+                    match resolve(req.device.as_deref()) {
+                        Some(Device::Sysfs(dev)) => set_sysfs(&mut conn, &dev, req.delta).await,
+                        Some(Device::Ddc(display)) => set_ddc(&mut ddc_levels, &display, req.delta),
+                        None => {}
+                    }
+                }
+            });
+        });
+        tx
+    })
+}
+
+/// Steps `device` (a backlight name or connector, or the preferred backlight)
+/// by `delta` percent.
+pub fn adjust(device: Option<&str>, delta: i32) {
+    let _ = writer().send(Adjust { device: device.map(str::to_string), delta });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_backlight(root: &Path, name: &str, kind: Option<&str>, max: u64, cur: u64) {
+        let dir = root.join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("max_brightness"), max.to_string()).unwrap();
+        std::fs::write(dir.join("brightness"), cur.to_string()).unwrap();
+        if let Some(kind) = kind {
+            std::fs::write(dir.join("type"), format!("{kind}\n")).unwrap();
+        }
+    }
+
+    fn fake_root(test: &str) -> PathBuf {
+        let root = std::env::temp_dir()
+            .join(format!("capsule-backlight-{}-{test}", std::process::id()))
+            .join("backlight");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[test]
+    fn devices_rank_firmware_then_platform_then_raw() {
+        let root = fake_root("order");
+        fake_backlight(&root, "intel_backlight", Some("raw"), 96000, 48000);
+        fake_backlight(&root, "acpi_video0", Some("firmware"), 100, 50);
+        fake_backlight(&root, "dell_backlight", Some("platform"), 15, 7);
+        fake_backlight(&root, "amdgpu_bl0", None, 255, 128);
+        fake_backlight(&root, "broken", Some("firmware"), 0, 0);
+        std::fs::create_dir_all(root.join("no_brightness")).unwrap();
+        std::fs::write(root.join("no_brightness/max_brightness"), "10").unwrap();
+
+        let names: Vec<String> = devices_in(&root).into_iter().map(|d| d.name).collect();
+        assert_eq!(names, ["acpi_video0", "dell_backlight", "amdgpu_bl0", "intel_backlight"]);
+
+        let _ = std::fs::remove_dir_all(root.parent().unwrap());
+    }
+
+    #[test]
+    fn percent_round_trips_through_raw() {
+        for max in [19393, 96000, 120000] {
+            for percent in MIN_PERCENT..=100 {
+                assert_eq!(raw_to_percent(percent_to_raw(percent, max), max), percent, "max {max}");
+            }
+        }
+        for max in [7, 15, 100, 255] {
+            let mut last = 0;
+            for percent in 0..=100 {
+                let raw = percent_to_raw(percent, max);
+                assert!(raw >= last && raw <= max, "max {max} percent {percent}");
+                last = raw;
+            }
+            assert_eq!(percent_to_raw(100, max), max);
+        }
+    }
+
+    #[test]
+    fn steps_respect_the_floor() {
+        for max in [15, 255, 96000] {
+            let floor = percent_to_raw(MIN_PERCENT, max);
+            assert_eq!(step_raw(floor, max, -5), floor);
+            assert_eq!(step_raw(max, max, 5), max);
+            assert!(step_raw(floor, max, 5) > floor);
+
+            let mut raw = max;
+            for _ in 0..100 {
+                let next = step_raw(raw, max, -5);
+                assert!(next <= raw && next >= floor);
+                raw = next;
+            }
+            assert_eq!(raw, floor);
+        }
+    }
+
+    #[test]
+    fn stepping_down_below_the_floor_never_brightens() {
+        let max = 96000;
+        let floor = percent_to_raw(MIN_PERCENT, max);
+        for raw in [0, 1, floor / 2, floor - 1] {
+            assert!(step_raw(raw, max, -5) <= raw, "raw {raw}");
+            assert!(step_raw(raw, max, 5) >= raw, "raw {raw}");
+        }
+    }
+}
@@ -1,13 +1,16 @@
 use gtk4::{
     Application, ApplicationWindow, Label, Box as GtkBox, Button, Orientation, prelude::*,
     DrawingArea, gdk_pixbuf::Pixbuf, Image, EventControllerScroll, EventControllerScrollFlags,
-    Switch,
+    Switch, Scale,
 };
 use gtk4::glib;
 use gtk4_layer_shell::{Edge, Layer, LayerShell};
 use std::time::Duration;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
+use crate::notifications::{dnd_enabled, dnd_scheduled, set_dnd};
+use crate::audio::{self, AudioDevice, AudioState, AudioStream};
+use std::collections::HashMap;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum NetworkState {
@@ -18,95 +21,19 @@ pub enum NetworkState {
     WifiOff,
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub struct SoundState {
-    pub volume:  u32,
-    pub muted:   bool,
-    pub sink:    String,
-}
-
-fn get_sound_state() -> SoundState {
-    let wpctl = std::process::Command::new("wpctl")
-        .args(["get-volume", "@DEFAULT_AUDIO_SINK@"])
-        .output();
-
-    let (volume, muted) = if let Ok(out) = wpctl {
-        let text = String::from_utf8_lossy(&out.stdout).to_string();
-        let is_muted = text.contains("[MUTED]");
-        let vol = text
-            .split_whitespace()
-            .nth(1)
-            .and_then(|v| v.parse::<f32>().ok())
-            .map(|v| (v * 100.0).round() as u32)
-            .unwrap_or(0);
-        (vol, is_muted)
-    } else {
-        (0, false)
-    };
-
-    let sink = std::process::Command::new("pactl")
-        .args(["get-default-sink"])
-        .output()
-        .ok()
-        .and_then(|o| {
-            let raw = String::from_utf8_lossy(&o.stdout).trim().to_string();
-            if raw.is_empty() { return None; }
-
-            let list = std::process::Command::new("pactl")
-                .args(["list", "sinks"])
-                .output()
-                .ok()?;
-            let list_text = String::from_utf8_lossy(&list.stdout).to_string();
-
-            let mut in_sink  = false;
-            let mut desc: Option<String> = None;
-            for line in list_text.lines() {
-                let trimmed = line.trim();
-                if trimmed.starts_with("Name:") {
-                    in_sink = trimmed.contains(&raw);
-                }
-                if in_sink {
-                    if let Some(d) = trimmed.strip_prefix("Description:") {
-                        desc = Some(d.trim().to_string());
-                        break;
-                    }
-                }
-            }
-            desc.or(Some(raw))
-        })
-        .unwrap_or_else(|| "Unknown Output".to_string());
-
-    SoundState { volume, muted, sink }
-}
-
-fn sound_icon(state: &SoundState) -> &'static str {
-    if state.muted || state.volume == 0 {
+fn sound_icon(sink: Option<&AudioDevice>) -> &'static str {
+    let Some(sink) = sink else { return "/var/lib/cynager/icons/soundmute.svg" };
+    if sink.muted || sink.volume == 0 {
         "/var/lib/cynager/icons/soundmute.svg"
-    } else if state.volume <= 33 {
+    } else if sink.volume <= 33 {
         "/var/lib/cynager/icons/soundlow.svg"
-    } else if state.volume <= 66 {
+    } else if sink.volume <= 66 {
         "/var/lib/cynager/icons/soundmed.svg"
     } else {
         "/var/lib/cynager/icons/soundhigh.svg"
     }
 }
 
-pub fn spawn_sound_watcher(interval: Duration) -> std::sync::mpsc::Receiver<SoundState> {
-    let (tx, rx) = std::sync::mpsc::channel::<SoundState>();
-    std::thread::spawn(move || {
-        let mut last: Option<SoundState> = None;
-        loop {
-            let state = get_sound_state();
-            if Some(&state) != last.as_ref() {
-                if tx.send(state.clone()).is_err() { break; }
-                last = Some(state);
-            }
-            std::thread::sleep(interval);
-        }
-    });
-    rx
-}
-
 fn wifi_soft_blocked() -> bool {
     let Ok(entries) = std::fs::read_dir("/sys/class/rfkill") else { return false };
     for entry in entries.flatten() {
@@ -319,6 +246,180 @@ fn get_wifi_networks() -> Vec<(String, String, bool)> {
     vec![]
 }
 
+// ─── mixer ────────────────────────────────────────────────────────────────────
+
+struct MixerRow {
+    container: GtkBox,
+    title:     Label,
+    slider:    Scale,
+    mute:      Button,
+    sinks:     gtk4::DropDown,
+    sink_names: Rc<RefCell<Vec<String>>>,
+    muted:     Rc<Cell<bool>>,
+    syncing:   Rc<Cell<bool>>,
+}
+
+fn stream_label(stream: &AudioStream) -> String {
+    if stream.title.is_empty() || stream.title == stream.app_name {
+        stream.app_name.clone()
+    } else {
+        format!("{} — {}", stream.app_name, stream.title)
+    }
+}
+
+fn build_mixer_row(stream: &AudioStream) -> MixerRow {
+    let icon = Image::new();
+    icon.set_icon_size(gtk4::IconSize::Normal);
+    let icon_theme = gtk4::IconTheme::for_display(&gtk4::gdk::Display::default().expect("no display"));
+    let icon_name  = stream.icon.clone().unwrap_or_else(|| stream.app_name.to_lowercase());
+    if icon_theme.has_icon(&icon_name) {
+        icon.set_icon_name(Some(&icon_name));
+    } else {
+        icon.set_from_file(Some("/var/lib/cynager/icons/soundhigh.svg"));
+    }
+
+    let title = Label::new(Some(&stream_label(stream)));
+    title.add_css_class("netListSSID");
+    title.set_hexpand(true);
+    title.set_halign(gtk4::Align::Start);
+    title.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+
+    let mute = Button::builder()
+        .label("Mute")
+        .css_classes(["notiActionBtn"])
+        .valign(gtk4::Align::Center)
+        .build();
+
+    let sinks = gtk4::DropDown::from_strings(&[]);
+    sinks.add_css_class("mixerSinks");
+    sinks.set_valign(gtk4::Align::Center);
+
+    let head = GtkBox::new(Orientation::Horizontal, 8);
+    head.append(&icon);
+    head.append(&title);
+    head.append(&sinks);
+    head.append(&mute);
+
+    let slider = Scale::with_range(Orientation::Horizontal, 0.0, 100.0, 1.0);
+    slider.set_draw_value(false);
+    slider.set_hexpand(true);
+    slider.add_css_class("soundSlider");
+
+    let container = GtkBox::new(Orientation::Vertical, 4);
+    container.add_css_class("mixerRow");
+    container.append(&head);
+    container.append(&slider);
+
+    let row = MixerRow {
+        container,
+        title,
+        slider,
+        mute,
+        sinks,
+        sink_names: Rc::new(RefCell::new(Vec::new())),
+        muted:      Rc::new(Cell::new(stream.muted)),
+        syncing:    Rc::new(Cell::new(false)),
+    };
+
+    let index = stream.index;
+    {
+        let syncing = row.syncing.clone();
+        row.slider.connect_value_changed(move |scale| {
+            if syncing.get() { return; }
+            audio::set_stream_volume(index, scale.value().round() as u32);
+        });
+    }
+    {
+        let syncing    = row.syncing.clone();
+        let sink_names = row.sink_names.clone();
+        row.sinks.connect_selected_notify(move |dd| {
+            if syncing.get() { return; }
+            if let Some(name) = sink_names.borrow().get(dd.selected() as usize) {
+                audio::move_stream(index, name);
+            }
+        });
+    }
+    {
+        let muted = row.muted.clone();
+        row.mute.connect_clicked(move |_| {
+            audio::set_stream_mute(index, !muted.get());
+        });
+    }
+    row
+}
+
+fn update_mixer_row(row: &MixerRow, stream: &AudioStream, sinks: &[AudioDevice]) {
+    row.syncing.set(true);
+
+    row.title.set_label(&stream_label(stream));
+    if (row.slider.value() - stream.volume.min(100) as f64).abs() >= 1.0 {
+        row.slider.set_value(stream.volume.min(100) as f64);
+    }
+    row.muted.set(stream.muted);
+    if stream.muted {
+        row.mute.add_css_class("mixerMuted");
+        row.mute.set_label("Unmute");
+    } else {
+        row.mute.remove_css_class("mixerMuted");
+        row.mute.set_label("Mute");
+    }
+
+    let names: Vec<String> = sinks.iter().map(|d| d.name.clone()).collect();
+    if *row.sink_names.borrow() != names {
+        let descriptions: Vec<&str> = sinks.iter().map(|d| d.description.as_str()).collect();
+        row.sinks.set_model(Some(&gtk4::StringList::new(&descriptions)));
+        *row.sink_names.borrow_mut() = names;
+    }
+    if let Some(pos) = sinks.iter().position(|d| d.index == stream.sink) {
+        row.sinks.set_selected(pos as u32);
+    }
+    row.sinks.set_visible(sinks.len() > 1);
+
+    row.syncing.set(false);
+}
+
+/// Per-application streams. Rows are kept across updates so a slider being
+/// dragged isn't rebuilt under the pointer.
+fn build_mixer_section() -> (GtkBox, impl Fn(&AudioState)) {
+    let title = Label::builder()
+        .label("Applications")
+        .css_classes(["soundPanelTitle"])
+        .halign(gtk4::Align::Start)
+        .build();
+
+    let list = GtkBox::new(Orientation::Vertical, 8);
+    list.add_css_class("netList");
+
+    let section = GtkBox::new(Orientation::Vertical, 6);
+    section.append(&title);
+    section.append(&list);
+    section.set_visible(false);
+
+    let rows: RefCell<HashMap<u32, MixerRow>> = RefCell::new(HashMap::new());
+    let section_c = section.clone();
+    let update = move |state: &AudioState| {
+        let mut rows = rows.borrow_mut();
+        rows.retain(|index, row| {
+            let alive = state.streams.iter().any(|s| s.index == *index);
+            if !alive { list.remove(&row.container); }
+            alive
+        });
+        for stream in &state.streams {
+            let row = rows.entry(stream.index).or_insert_with(|| {
+                let row = build_mixer_row(stream);
+                list.append(&row.container);
+                row
+            });
+            update_mixer_row(row, stream, &state.sinks);
+        }
+        section_c.set_visible(!state.streams.is_empty());
+    };
+    (section, update)
+}
+
+/// How often the DnD button re-checks the schedule; windows are minute-granular.
+const DND_REFRESH_SECS: u32 = 30;
+
 pub fn spawn_ctrl_capsules(
     app:          &Application,
     overlay_open: Rc<RefCell<bool>>,
@@ -679,17 +780,14 @@ pub fn spawn_ctrl_capsules(
         });
     }
 
-    let sound_rx = spawn_sound_watcher(Duration::from_secs(3));
-    let init_snd = get_sound_state();
-
-    let snd_icon = Image::from_file(sound_icon(&init_snd));
+    let snd_icon = Image::from_file(sound_icon(None));
     snd_icon.set_icon_size(gtk4::IconSize::Large);
 
-    let snd_label = Label::new(Some(&format!("{}%", init_snd.volume)));
+    let snd_label = Label::new(Some("–"));
     snd_label.add_css_class("netBtnLabel");
     snd_label.set_halign(gtk4::Align::Start);
 
-    let snd_body = Label::new(Some(&init_snd.sink));
+    let snd_body = Label::new(Some("No output"));
     snd_body.add_css_class("netBtnBody");
     snd_body.set_halign(gtk4::Align::Start);
 
@@ -709,34 +807,195 @@ pub fn spawn_ctrl_capsules(
         .css_classes(["ctrlBtnL"])
         .build();
 
-    let snd_icon_rc = Rc::new(snd_icon);
-    let snd_label_rc = Rc::new(snd_label);
-    let snd_body_rc = Rc::new(snd_body);
-    let sound_rx = Rc::new(RefCell::new(sound_rx));
+    let sound_expanded = Rc::new(RefCell::new(false));
+
+    let build_device_section = |title: &str| {
+        let title_lbl = Label::builder()
+            .label(title)
+            .css_classes(["soundPanelTitle"])
+            .halign(gtk4::Align::Start)
+            .hexpand(true)
+            .build();
+        let mute_switch = Switch::builder()
+            .css_classes(["netPanelSwitch"])
+            .tooltip_text("Mute")
+            .valign(gtk4::Align::Center)
+            .build();
+        let head = GtkBox::new(Orientation::Horizontal, 8);
+        head.add_css_class("netPanelActions");
+        head.append(&title_lbl);
+        head.append(&Label::builder().label("Mute").css_classes(["netBtnBody"]).build());
+        head.append(&mute_switch);
+
+        let slider = Scale::with_range(Orientation::Horizontal, 0.0, 100.0, 1.0);
+        slider.set_draw_value(false);
+        slider.set_hexpand(true);
+        slider.add_css_class("soundSlider");
+
+        let list = GtkBox::new(Orientation::Vertical, 2);
+        list.add_css_class("netList");
+
+        let section = GtkBox::new(Orientation::Vertical, 6);
+        section.append(&head);
+        section.append(&slider);
+        section.append(&list);
+        (section, mute_switch, slider, list)
+    };
 
-    {
-        let snd_icon_rc = snd_icon_rc.clone();
-        let snd_label_rc = snd_label_rc.clone();
-        let snd_body_rc = snd_body_rc.clone();
-        let sound_rx = sound_rx.clone();
-        let overlay_open = overlay_open.clone();
+    let (out_section, out_mute, out_slider, out_list) = build_device_section("Output");
+    let (mic_section, mic_mute, mic_slider, mic_list) = build_device_section("Microphone");
+
+    let sound_panel = GtkBox::new(Orientation::Vertical, 12);
+    sound_panel.add_css_class("netPanel");
+    sound_panel.add_css_class("soundPanel");
+    let (mixer_section, update_mixer) = build_mixer_section();
+
+    sound_panel.append(&out_section);
+    sound_panel.append(&mic_section);
+    sound_panel.append(&mixer_section);
+    sound_panel.set_visible(false);
+
+    // Set while the panel writes server state into its own widgets, so the
+    // slider/switch handlers don't echo it back to PulseAudio.
+    let sound_syncing:  Rc<Cell<bool>>    = Rc::new(Cell::new(false));
+    let default_sink:   Rc<RefCell<String>> = Rc::new(RefCell::new(String::new()));
+    let default_source: Rc<RefCell<String>> = Rc::new(RefCell::new(String::new()));
+
+    // Rows are only rebuilt when the devices or the default change, not on every
+    // volume step, so a row isn't destroyed under the pointer.
+    type DeviceKey = Vec<(String, String, bool)>;
+    let out_shown: RefCell<Option<DeviceKey>> = RefCell::new(None);
+    let mic_shown: RefCell<Option<DeviceKey>> = RefCell::new(None);
+
+    let fill_devices = |list: &GtkBox, shown: &RefCell<Option<DeviceKey>>, devices: &[AudioDevice], set_default: fn(&str)| {
+        let key: DeviceKey = devices
+            .iter()
+            .map(|d| (d.name.clone(), d.description.clone(), d.is_default))
+            .collect();
+        if shown.borrow().as_ref() == Some(&key) {
+            return;
+        }
+        *shown.borrow_mut() = Some(key);
 
-        glib::timeout_add_local(Duration::from_millis(500), move || {
-            if !*overlay_open.borrow() {
-                return glib::ControlFlow::Break;
+        while let Some(child) = list.first_child() {
+            list.remove(&child);
+        }
+        if devices.is_empty() {
+            let row = Label::new(Some("No devices"));
+            row.add_css_class("netListEmpty");
+            list.append(&row);
+            return;
+        }
+        for device in devices {
+            let row_box = GtkBox::new(Orientation::Horizontal, 10);
+            row_box.add_css_class("netListRow");
+            if device.is_default {
+                let current = Label::new(Some("•"));
+                current.add_css_class("netListConnected");
+                row_box.append(&current);
             }
-            let rx = sound_rx.borrow();
-            let mut latest: Option<SoundState> = None;
-            while let Ok(state) = rx.try_recv() {
-                latest = Some(state);
+            let name_lbl = Label::new(Some(&device.description));
+            name_lbl.set_hexpand(true);
+            name_lbl.set_halign(gtk4::Align::Start);
+            name_lbl.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+            name_lbl.add_css_class("netListSSID");
+            row_box.append(&name_lbl);
+
+            let row_btn = Button::builder()
+                .child(&row_box)
+                .css_classes(["netListRowBtn"])
+                .build();
+            let name = device.name.clone();
+            row_btn.connect_clicked(move |_| set_default(&name));
+            list.append(&row_btn);
+        }
+    };
+
+    let set_slider = |slider: &Scale, volume: u32| {
+        if (slider.value() - volume.min(100) as f64).abs() >= 1.0 {
+            slider.set_value(volume.min(100) as f64);
+        }
+    };
+
+    let sound_listener = {
+        let sound_syncing  = sound_syncing.clone();
+        let default_sink   = default_sink.clone();
+        let default_source = default_source.clone();
+        let snd_icon  = snd_icon.clone();
+        let snd_label = snd_label.clone();
+        let snd_body  = snd_body.clone();
+        let out_mute   = out_mute.clone();
+        let out_slider = out_slider.clone();
+        let mic_mute   = mic_mute.clone();
+        let mic_slider = mic_slider.clone();
+        let mic_section = mic_section.clone();
+        audio::subscribe(move |state| {
+            let sink = state.default_sink();
+            snd_icon.set_from_file(Some(sound_icon(sink)));
+            snd_label.set_label(&sink.map(|s| format!("{}%", s.volume)).unwrap_or_else(|| "–".into()));
+            snd_body.set_label(sink.map(|s| s.description.as_str()).unwrap_or("No output"));
+
+            sound_syncing.set(true);
+            if let Some(sink) = sink {
+                *default_sink.borrow_mut() = sink.name.clone();
+                set_slider(&out_slider, sink.volume);
+                out_mute.set_active(sink.muted);
             }
-            drop(rx);
-            if let Some(state) = latest {
-                snd_icon_rc.set_from_file(Some(sound_icon(&state)));
-                snd_label_rc.set_label(&format!("{}%", state.volume));
-                snd_body_rc.set_label(&state.sink);
+            if let Some(source) = state.default_source() {
+                *default_source.borrow_mut() = source.name.clone();
+                set_slider(&mic_slider, source.volume);
+                mic_mute.set_active(source.muted);
             }
-            glib::ControlFlow::Continue
+            sound_syncing.set(false);
+
+            fill_devices(&out_list, &out_shown, &state.sinks, audio::set_default_sink);
+            fill_devices(&mic_list, &mic_shown, &state.sources, audio::set_default_source);
+            mic_section.set_visible(!state.sources.is_empty());
+            update_mixer(state);
+        })
+    };
+
+    if let Some(id) = sound_listener {
+        win.connect_destroy(move |_| audio::unsubscribe(id));
+    }
+
+    {
+        let sound_syncing = sound_syncing.clone();
+        let default_sink  = default_sink.clone();
+        out_slider.connect_value_changed(move |scale| {
+            if sound_syncing.get() { return; }
+            audio::set_sink_volume(&default_sink.borrow(), scale.value().round() as u32);
+        });
+    }
+
+    {
+        let sound_syncing  = sound_syncing.clone();
+        let default_source = default_source.clone();
+        mic_slider.connect_value_changed(move |scale| {
+            if sound_syncing.get() { return; }
+            audio::set_source_volume(&default_source.borrow(), scale.value().round() as u32);
+        });
+    }
+
+    {
+        let sound_syncing = sound_syncing.clone();
+        let default_sink  = default_sink.clone();
+        out_mute.connect_state_set(move |_, muted| {
+            if !sound_syncing.get() {
+                audio::set_sink_mute(&default_sink.borrow(), muted);
+            }
+            glib::Propagation::Proceed
+        });
+    }
+
+    {
+        let sound_syncing  = sound_syncing.clone();
+        let default_source = default_source.clone();
+        mic_mute.connect_state_set(move |_, muted| {
+            if !sound_syncing.get() {
+                audio::set_source_mute(&default_source.borrow(), muted);
+            }
+            glib::Propagation::Proceed
         });
     }
 
@@ -757,9 +1016,25 @@ pub fn spawn_ctrl_capsules(
     let dnd: Button = Button::builder()
         .child(&dndicon)
         .css_classes(["ctrlBtnS"])
-        .tooltip_text("Airplane Mode")
+        .tooltip_text("Do Not Disturb")
         .build();
 
+    let refresh_dnd = |btn: &Button| {
+        if dnd_enabled() {
+            btn.add_css_class("dndOn");
+        } else {
+            btn.remove_css_class("dndOn");
+        }
+        if dnd_scheduled() {
+            btn.add_css_class("dndScheduled");
+            btn.set_tooltip_text(Some("Do Not Disturb (scheduled)"));
+        } else {
+            btn.remove_css_class("dndScheduled");
+            btn.set_tooltip_text(Some("Do Not Disturb"));
+        }
+    };
+    refresh_dnd(&dnd);
+
     let setticon = Image::from_file("/var/lib/cynager/icons/cog.svg");
     setticon.set_icon_size(gtk4::IconSize::Large);
 
@@ -788,6 +1063,7 @@ pub fn spawn_ctrl_capsules(
     ctrl_column.set_valign(gtk4::Align::Start);
     ctrl_column.append(&btns);
     ctrl_column.append(&*net_panel_rc);
+    ctrl_column.append(&sound_panel);
 
     let layout = gtk4::Overlay::new();
     layout.set_child(Some(&backdrop));
@@ -855,6 +1131,20 @@ pub fn spawn_ctrl_capsules(
         });
     }
 
+    {
+        dnd.connect_clicked(move |btn| {
+            set_dnd(!dnd_enabled());
+            refresh_dnd(btn);
+        });
+        // Schedule windows open and close on their own; follow them without a click.
+        let dnd_weak = dnd.downgrade();
+        glib::timeout_add_seconds_local(DND_REFRESH_SECS, move || {
+            let Some(btn) = dnd_weak.upgrade() else { return glib::ControlFlow::Break };
+            refresh_dnd(&btn);
+            glib::ControlFlow::Continue
+        });
+    }
+
     {
         setting.connect_clicked(move |_| {
             // let _ = std::process::Command::new("nm-connection-editor").spawn();
@@ -863,39 +1153,28 @@ pub fn spawn_ctrl_capsules(
     }
 
     {
-        soundbtn.connect_clicked(move |_| {
-            // let _ = std::process::Command::new("pavucontrol").spawn();
-            close();
+        let sound_panel    = sound_panel.clone();
+        let sound_expanded = sound_expanded.clone();
+        soundbtn.connect_clicked(move |btn| {
+            let mut expanded = sound_expanded.borrow_mut();
+            *expanded = !*expanded;
+            if *expanded {
+                btn.add_css_class("netBtnExpanded");
+                sound_panel.set_visible(true);
+            } else {
+                btn.remove_css_class("netBtnExpanded");
+                sound_panel.set_visible(false);
+            }
         });
     }
 
     {
-        let snd_icon_rc  = snd_icon_rc.clone();
-        let snd_label_rc = snd_label_rc.clone();
-        let snd_body_rc  = snd_body_rc.clone();
-
         let scroll = EventControllerScroll::new(
             EventControllerScrollFlags::VERTICAL | EventControllerScrollFlags::DISCRETE,
         );
 
         scroll.connect_scroll(move |_, _dx, dy| {
-            let step = if dy < 0.0 { "5%+" } else { "5%-" };
-            let _ = std::process::Command::new("wpctl")
-                .args(["set-volume", "-l", "1.0", "@DEFAULT_AUDIO_SINK@", step])
-                .spawn();
-
-            glib::timeout_add_local_once(Duration::from_millis(80), {
-                let snd_icon_rc  = snd_icon_rc.clone();
-                let snd_label_rc = snd_label_rc.clone();
-                let snd_body_rc  = snd_body_rc.clone();
-                move || {
-                    let state = get_sound_state();
-                    snd_icon_rc.set_from_file(Some(sound_icon(&state)));
-                    snd_label_rc.set_label(&format!("{}%", state.volume));
-                    snd_body_rc.set_label(&state.sink);
-                }
-            });
-
+            audio::adjust_sink_volume(if dy < 0.0 { 5 } else { -5 });
             glib::Propagation::Stop
         });
 
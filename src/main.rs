@@ -12,6 +12,8 @@ use std::rc::Rc;
 use libc;
 use niri_ipc::{socket::Socket, Action, PositionChange, Request, Response, WorkspaceReferenceArg};
 
+mod audio;
+mod backlight;
 mod notifications;
 mod osd;
 mod ssd;
@@ -330,7 +332,7 @@ fn spawn_battery_watcher(interval: Duration) -> std::sync::mpsc::Receiver<Option
 }
 
 fn coping_with(app: &Application) {
-    let rx = notifications::spawn_messaging_daemon();
+    let (rx, noti_signals) = notifications::spawn_messaging_daemon();
 
     let css      = CssProvider::new();
     let home_dir = env::var("HOME").unwrap_or_else(|_| ".".to_string());
@@ -430,8 +432,17 @@ fn coping_with(app: &Application) {
     badge.set_max_width_chars(100);
     badge.set_ellipsize(gtk4::pango::EllipsizeMode::End);
 
+    let badge_actions = GtkBox::new(Orientation::Horizontal, 5);
+    badge_actions.set_css_classes(&["badgeActions"]);
+    badge_actions.set_halign(gtk4::Align::Start);
+    badge_actions.set_visible(false);
+
+    let badge_progress = notifications::build_progress_bar(notifications::BADGE_PROGRESS_WIDTH);
+
     badge_container.append(&badge_head);
     badge_container.append(&badge);
+    badge_container.append(&badge_progress);
+    badge_container.append(&badge_actions);
 
     let osd_box = GtkBox::new(Orientation::Vertical, 5);
     osd_box.set_hexpand(true);
@@ -487,6 +498,51 @@ fn coping_with(app: &Application) {
         });
     }
 
+    audio::start();
+
+    let mic_dot = Label::builder()
+        .label("●")
+        .css_classes(["micDot"])
+        .build();
+
+    let mic_indicator = Button::builder()
+        .child(&mic_dot)
+        .css_classes(["micBtn"])
+        .has_tooltip(true)
+        .visible(false)
+        .build();
+    mic_indicator.set_cursor_from_name(Some("pointer"));
+    mic_indicator.connect_clicked(|_| audio::toggle_source_mute());
+
+    {
+        let mic_c = mic_indicator.clone();
+        audio::subscribe(move |state| {
+            if state.captures.is_empty() {
+                mic_c.set_visible(false);
+                return;
+            }
+            let mut apps: Vec<&str> = Vec::new();
+            for capture in &state.captures {
+                if !apps.contains(&capture.app_name.as_str()) {
+                    apps.push(&capture.app_name);
+                }
+            }
+            let muted = state.default_source().is_some_and(|s| s.muted);
+            if muted {
+                mic_c.add_css_class("micMuted");
+            } else {
+                mic_c.remove_css_class("micMuted");
+            }
+            mic_c.set_tooltip_text(Some(&format!(
+                "Microphone {} by {}\nClick to {}",
+                if muted { "muted, held" } else { "in use" },
+                apps.join(", "),
+                if muted { "unmute" } else { "mute" },
+            )));
+            mic_c.set_visible(true);
+        });
+    }
+
     let initial_bat_state = get_battery_state();
     let has_battery = initial_bat_state.is_some();
 
@@ -1021,6 +1077,7 @@ fn coping_with(app: &Application) {
     time_capsule.append(&cos);
     time_capsule.append(&badge_container);
     time_capsule.append(&c_tna);
+    time_capsule.append(&mic_indicator);
     time_capsule.append(&network);
     if has_battery {
         time_capsule.append(&battery);
@@ -1096,8 +1153,19 @@ fn coping_with(app: &Application) {
     noti_panel_window.present();
 
     notifications::connect_notifications_to_dock(
-        rx, &time_capsule, &time_window, &cos_logo, &cos, &badge, &badge_head,
-        &noti_boxy_inner_notifications_all,
+        rx,
+        notifications::DockWidgets {
+            noti_window:    time_capsule.clone(),
+            main_window:    time_window.clone(),
+            app_img:        cos_logo.clone(),
+            cos_btn:        cos.clone(),
+            badge:          badge.clone(),
+            badge_head:     badge_head.clone(),
+            badge_actions:  badge_actions.clone(),
+            badge_progress: badge_progress.clone(),
+            noti_all:       noti_boxy_inner_notifications_all.clone(),
+        },
+        noti_signals,
     );
     osd::connect_osd_to_dock(&osd, &osd_revealer, &osd_capsule, &osd_window, &lbl);
 
@@ -1,17 +1,25 @@
 use zbus::connection::Builder;
+use zbus::SignalContext;
+use zbus::export::futures_util::StreamExt;
+use zbus::fdo::{DBusProxy, RequestNameFlags, RequestNameReply};
+use zbus::zvariant::Value;
 use tokio::sync::mpsc;
 use gtk4::{ApplicationWindow, Box as GtkBox, Button, Image, Label, glib, prelude::*};
 use gtk4::glib::clone;
-use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 use std::cell::{RefCell, Cell};
 use std::rc::Rc;
-use std::collections::VecDeque;
-use rodio::{Decoder, OutputStream, Sink};
+use std::collections::{HashMap, VecDeque};
+use rodio::{Decoder, OutputStream, Source};
+use rodio::source::Buffered;
 use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
 use std::thread;
 use std::io::{self, BufRead, BufReader};
 use std::time::Duration;
 use gtk4_layer_shell::LayerShell;
+use regex::Regex;
 
 #[derive(Debug, Clone)]
 pub struct Notification {
@@ -20,12 +28,158 @@ pub struct Notification {
     pub summary: String,
     pub body: String,
     pub icon: String,
-    pub _timestamp: std::time::Instant,
-    pub _actions: Vec<String>
+    pub image: Option<NotificationImage>,
+    pub timestamp: std::time::SystemTime,
+    pub actions: Vec<(String, String)>,
+    pub resident: bool,
+    pub transient: bool,
+    pub urgency: Urgency,
+    pub category: Option<String>,
+    pub expire_timeout: i32,
+    pub sound_file: Option<String>,
+    pub sound_name: Option<String>,
+    pub suppress_sound: bool,
+    pub value: Option<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub enum NotificationImage {
+    Path(String),
+    Data {
+        width:     i32,
+        height:    i32,
+        rowstride: i32,
+        has_alpha: bool,
+        data:      Vec<u8>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+#[derive(Debug, Clone)]
+pub enum NotificationEvent {
+    Notify(Box<Notification>),
+    Close(u32),
+    State(ServerState),
+}
+
+/// Whether capsule currently owns `org.freedesktop.Notifications`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerState {
+    Active,
+    Waiting { owner: Option<String> },
+    Unavailable(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CloseReason {
+    Expired      = 1,
+    Dismissed    = 2,
+    ClosedByCall = 3,
+    Undefined    = 4,
+}
+
+#[derive(Debug, Clone)]
+pub enum ServerSignal {
+    ActionInvoked { id: u32, action_key: String, token: Option<String> },
+    Closed        { id: u32, reason: CloseReason },
+    Replied       { id: u32, text: String },
+}
+
+const NOTIFICATIONS_PATH: &str = "/org/freedesktop/Notifications";
+const NOTIFICATIONS_NAME: &str = "org.freedesktop.Notifications";
+
+fn hint_u8(hints: &std::collections::HashMap<String, zbus::zvariant::OwnedValue>, key: &str) -> Option<u8> {
+    match &**hints.get(key)? {
+        Value::U8(v) => Some(*v),
+        _ => None,
+    }
+}
+
+fn hint_i32(hints: &std::collections::HashMap<String, zbus::zvariant::OwnedValue>, key: &str) -> Option<i32> {
+    match &**hints.get(key)? {
+        Value::I32(v) => Some(*v),
+        Value::U32(v) => i32::try_from(*v).ok(),
+        Value::U8(v)  => Some(*v as i32),
+        _ => None,
+    }
+}
+
+fn hint_str(hints: &std::collections::HashMap<String, zbus::zvariant::OwnedValue>, key: &str) -> Option<String> {
+    match &**hints.get(key)? {
+        Value::Str(v) => Some(v.as_str().to_string()),
+        _ => None,
+    }
+}
+
+/// Decodes the `(iiibiiay)` raw image hint. Only 8-bit RGB/RGBA data is accepted.
+fn hint_image_data(hints: &std::collections::HashMap<String, zbus::zvariant::OwnedValue>, key: &str) -> Option<NotificationImage> {
+    let Value::Structure(st) = &**hints.get(key)? else { return None };
+    let [
+        Value::I32(width),
+        Value::I32(height),
+        Value::I32(rowstride),
+        Value::Bool(has_alpha),
+        Value::I32(bits_per_sample),
+        Value::I32(channels),
+        Value::Array(bytes),
+    ] = st.fields() else { return None };
+
+    let expected_channels = if *has_alpha { 4 } else { 3 };
+    if *bits_per_sample != 8 || *channels != expected_channels || *width <= 0 || *height <= 0 {
+        return None;
+    }
+
+    // A stride shorter than one row of pixels makes GDK refuse the texture.
+    let row_bytes = (*width as usize).checked_mul(*channels as usize)?;
+    let rowstride_len = usize::try_from(*rowstride).ok()?;
+    if rowstride_len < row_bytes {
+        return None;
+    }
+    let needed = rowstride_len
+        .checked_mul(*height as usize - 1)?
+        .checked_add(row_bytes)?;
+
+    let data: Vec<u8> = bytes
+        .inner()
+        .iter()
+        .filter_map(|v| match v {
+            Value::U8(b) => Some(*b),
+            _ => None,
+        })
+        .collect();
+
+    if data.len() < needed {
+        return None;
+    }
+
+    Some(NotificationImage::Data {
+        width:     *width,
+        height:    *height,
+        rowstride: *rowstride,
+        has_alpha: *has_alpha,
+        data,
+    })
+}
+
+fn is_image_path(s: &str) -> bool {
+    s.starts_with("file://") || s.starts_with('/')
+}
+
+fn hint_bool(hints: &std::collections::HashMap<String, zbus::zvariant::OwnedValue>, key: &str) -> Option<bool> {
+    match &**hints.get(key)? {
+        Value::Bool(b) => Some(*b),
+        _ => None,
+    }
 }
 
 struct NotificationServer {
-    sender: mpsc::UnboundedSender<Notification>,
+    sender: mpsc::UnboundedSender<NotificationEvent>,
     next_id: std::sync::atomic::AtomicU32,
 }
 
@@ -34,90 +188,631 @@ impl NotificationServer {
     async fn notify(
         &self,
         app_name: &str,
-        _replaces_id: u32,
+        replaces_id: u32,
         app_icon: &str,
         summary: &str,
         body: &str,
-        _actions: Vec<String>,
+        actions: Vec<String>,
         hints: std::collections::HashMap<String, zbus::zvariant::OwnedValue>,
-        _expire_timeout: i32,
+        expire_timeout: i32,
     ) -> u32 {
-        let id = self
-            .next_id
-            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let id = if replaces_id != 0
+            && replaces_id < self.next_id.load(std::sync::atomic::Ordering::Relaxed)
+        {
+            replaces_id
+        } else {
+            self.next_id.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        };
 
-        let icon = if !app_icon.is_empty() {
+        let image_path = hint_str(&hints, "image-path").or_else(|| hint_str(&hints, "image_path"));
+
+        let icon = if let Some(name) = image_path.as_ref().filter(|p| !is_image_path(p)) {
+            name.clone()
+        } else if !app_icon.is_empty() && !is_image_path(app_icon) {
             app_icon.to_string()
-        } else if let Some(val) = hints.get("image-path") {
-            val.to_string().trim_matches('"').to_string()
-        } else if let Some(val) = hints.get("app-icon") {
-            val.to_string().trim_matches('"').to_string()
+        } else if let Some(name) = hint_str(&hints, "app-icon") {
+            name
         } else {
             app_name.to_lowercase()
         };
 
+        let image = hint_image_data(&hints, "image-data")
+            .or_else(|| hint_image_data(&hints, "image_data"))
+            .or_else(|| {
+                image_path
+                    .filter(|p| is_image_path(p))
+                    .map(NotificationImage::Path)
+            })
+            .or_else(|| is_image_path(app_icon).then(|| NotificationImage::Path(app_icon.to_string())))
+            .or_else(|| hint_image_data(&hints, "icon_data"));
+
+        let actions = actions
+            .chunks(2)
+            .filter_map(|pair| match pair {
+                [key, label] => Some((key.clone(), label.clone())),
+                _ => None,
+            })
+            .collect();
+
         let notif = Notification {
             id,
             app_name: app_name.to_string(),
             summary: summary.to_string(),
             body: body.to_string(),
             icon,
-            _timestamp: std::time::Instant::now(),
-            _actions,
+            image,
+            timestamp: std::time::SystemTime::now(),
+            actions,
+            resident: hint_bool(&hints, "resident").unwrap_or(false),
+            transient: hint_bool(&hints, "transient").unwrap_or(false),
+            urgency: match hint_u8(&hints, "urgency") {
+                Some(0) => Urgency::Low,
+                Some(2) => Urgency::Critical,
+                _ => Urgency::Normal,
+            },
+            category: hint_str(&hints, "category"),
+            expire_timeout,
+            sound_file: hint_str(&hints, "sound-file"),
+            sound_name: hint_str(&hints, "sound-name"),
+            suppress_sound: hint_bool(&hints, "suppress-sound").unwrap_or(false),
+            value: hint_i32(&hints, "value").map(|v| v.clamp(0, 100) as u8),
         };
 
-        let _ = self.sender.send(notif);
+        let _ = self.sender.send(NotificationEvent::Notify(Box::new(notif)));
         id
     }
 
     async fn get_capabilities(&self) -> Vec<String> {
-        vec!["body".into(), "persistence".into()]
+        vec![
+            "actions".into(),
+            "body".into(),
+            "body-hyperlinks".into(),
+            "body-markup".into(),
+            "inline-reply".into(),
+            "persistence".into(),
+            "sound".into(),
+        ]
     }
 
     async fn get_server_information(&self) -> (&str, &str, &str, &str) {
         ("capsule", "ekah", "1.0", "1.2")
     }
 
-    async fn close_notification(&self, _id: u32) {}
+    async fn close_notification(&self, id: u32) {
+        let _ = self.sender.send(NotificationEvent::Close(id));
+    }
+
+    #[zbus(signal)]
+    async fn action_invoked(ctxt: &SignalContext<'_>, id: u32, action_key: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn notification_closed(ctxt: &SignalContext<'_>, id: u32, reason: u32) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn activation_token(ctxt: &SignalContext<'_>, id: u32, activation_token: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn notification_replied(ctxt: &SignalContext<'_>, id: u32, text: &str) -> zbus::Result<()>;
+}
+
+async fn emit_signal(ctxt: &SignalContext<'_>, signal: ServerSignal) -> zbus::Result<()> {
+    match signal {
+        ServerSignal::ActionInvoked { id, action_key, token } => {
+            if let Some(token) = token {
+                NotificationServer::activation_token(ctxt, id, &token).await?;
+            }
+            NotificationServer::action_invoked(ctxt, id, &action_key).await
+        }
+        ServerSignal::Closed { id, reason } => {
+            NotificationServer::notification_closed(ctxt, id, reason as u32).await
+        }
+        ServerSignal::Replied { id, text } => {
+            NotificationServer::notification_replied(ctxt, id, &text).await
+        }
+    }
+}
+
+/// Best-effort process name of whoever holds the notifications name, e.g. "mako".
+async fn name_owner_process(dbus: &DBusProxy<'_>) -> Option<String> {
+    let name  = zbus::names::BusName::try_from(NOTIFICATIONS_NAME).ok()?;
+    let owner = dbus.get_name_owner(name).await.ok()?;
+    let pid   = dbus.get_connection_unix_process_id(owner.into_inner().into()).await.ok()?;
+    std::fs::read_to_string(format!("/proc/{pid}/comm"))
+        .ok()
+        .map(|comm| comm.trim().to_string())
+}
+
+/// Serves the interface, then queues for the well-known name instead of failing
+/// when another daemon already owns it; the bus hands it over once released.
+async fn run_messaging_daemon(
+    server:     NotificationServer,
+    mut sig_rx: UnboundedReceiver<ServerSignal>,
+    events:     UnboundedSender<NotificationEvent>,
+) -> zbus::Result<()> {
+    let conn = Builder::session()?
+        .serve_at(NOTIFICATIONS_PATH, server)?
+        .build()
+        .await?;
+
+    let dbus         = DBusProxy::new(&conn).await?;
+    let mut acquired = dbus.receive_name_acquired().await?;
+    let mut lost     = dbus.receive_name_lost().await?;
+
+    let reply = conn
+        .request_name_with_flags(
+            NOTIFICATIONS_NAME,
+            RequestNameFlags::ReplaceExisting | RequestNameFlags::AllowReplacement,
+        )
+        .await?;
+
+    let state = match reply {
+        RequestNameReply::PrimaryOwner | RequestNameReply::AlreadyOwner => ServerState::Active,
+        RequestNameReply::InQueue | RequestNameReply::Exists => {
+            let owner = name_owner_process(&dbus).await;
+            eprintln!(
+                "[notifications] {} is owned by {}, waiting for it to be released",
+                NOTIFICATIONS_NAME,
+                owner.as_deref().unwrap_or("another daemon"),
+            );
+            ServerState::Waiting { owner }
+        }
+    };
+    let _ = events.send(NotificationEvent::State(state));
+
+    let ctxt = SignalContext::new(&conn, NOTIFICATIONS_PATH)?;
+    loop {
+        tokio::select! {
+            signal = sig_rx.recv() => {
+                let Some(signal) = signal else { break };
+                if let Err(e) = emit_signal(&ctxt, signal).await {
+                    eprintln!("[notifications] failed to emit signal: {e}");
+                }
+            }
+            Some(signal) = acquired.next() => {
+                if signal.args().is_ok_and(|a| a.name() == NOTIFICATIONS_NAME) {
+                    let _ = events.send(NotificationEvent::State(ServerState::Active));
+                }
+            }
+            Some(signal) = lost.next() => {
+                if signal.args().is_ok_and(|a| a.name() == NOTIFICATIONS_NAME) {
+                    let owner = name_owner_process(&dbus).await;
+                    let _ = events.send(NotificationEvent::State(ServerState::Waiting { owner }));
+                }
+            }
+        }
+    }
+
+    std::future::pending::<()>().await;
+    Ok(())
 }
 
-pub fn spawn_messaging_daemon() -> UnboundedReceiver<Notification> {
+pub fn spawn_messaging_daemon() -> (UnboundedReceiver<NotificationEvent>, UnboundedSender<ServerSignal>) {
     let (tx, rx) = mpsc::unbounded_channel();
+    let (sig_tx, sig_rx) = mpsc::unbounded_channel::<ServerSignal>();
+    let first_id = load_history().iter().map(|n| n.id).max().unwrap_or(0) + 1;
 
     std::thread::spawn(move || {
-        tokio::runtime::Runtime::new()
-            .expect("tokio runtime")
-            .block_on(async move {
-                let server = NotificationServer {
-                    sender: tx,
-                    next_id: std::sync::atomic::AtomicU32::new(1),
-                };
+        let runtime = match tokio::runtime::Runtime::new() {
+            Ok(rt) => rt,
+            Err(e) => {
+                eprintln!("[notifications] failed to start runtime: {e}");
+                return;
+            }
+        };
+        runtime.block_on(async move {
+            let server = NotificationServer {
+                sender: tx.clone(),
+                next_id: std::sync::atomic::AtomicU32::new(first_id),
+            };
 
-                let _conn = Builder::session()
-                    .expect("dbus session")
-                    .name("org.freedesktop.Notifications")
-                    .expect("dbus name")
-                    .serve_at("/org/freedesktop/Notifications", server)
-                    .expect("serve_at")
-                    .build()
-                    .await
-                    .expect("dbus connection");
-
-                std::future::pending::<()>().await;
-            });
+            if let Err(e) = run_messaging_daemon(server, sig_rx, tx.clone()).await {
+                eprintln!("[notifications] daemon stopped: {e}");
+                let _ = tx.send(NotificationEvent::State(ServerState::Unavailable(e.to_string())));
+            }
+        });
     });
 
-    rx
+    (rx, sig_tx)
 }
 
-fn play_notification_sound() {
-    let file = match File::open("/var/lib/cynager/info.probe") {
-        Ok(f) => f,
-        Err(_) => return,
-    };
+fn activation_token() -> Option<String> {
+    let display = gtk4::gdk::Display::default()?;
+    display
+        .app_launch_context()
+        .startup_notify_id(None::<&gtk4::gio::AppInfo>, &[])
+        .map(|t| t.to_string())
+}
+
+fn invoke_action(signals: &UnboundedSender<ServerSignal>, id: u32, action_key: &str) {
+    let _ = signals.send(ServerSignal::ActionInvoked {
+        id,
+        action_key: action_key.to_string(),
+        token:      activation_token(),
+    });
+}
+
+/// Layer-shell surfaces don't take keyboard focus by default, so the reply
+/// entry asks for it while it is being typed into and gives it back after.
+fn set_entry_keyboard(entry: &gtk4::Entry, mode: gtk4_layer_shell::KeyboardMode) {
+    if let Some(window) = entry.root().and_downcast::<gtk4::Window>() {
+        window.set_keyboard_mode(mode);
+    }
+}
+
+fn build_reply_entry(
+    notif:      &Notification,
+    label:      &str,
+    signals:    &UnboundedSender<ServerSignal>,
+    on_invoked: Rc<dyn Fn()>,
+) -> gtk4::Entry {
+    let placeholder = if label.is_empty() || label == "inline-reply" { "Reply…" } else { label };
+    let entry = gtk4::Entry::builder()
+        .placeholder_text(placeholder)
+        .css_classes(["notiReplyEntry"])
+        .valign(gtk4::Align::Center)
+        .width_chars(18)
+        .build();
+
+    let click = gtk4::GestureClick::new();
+    click.set_propagation_phase(gtk4::PropagationPhase::Capture);
+    {
+        let entry = entry.clone();
+        click.connect_pressed(move |_, _, _, _| {
+            set_entry_keyboard(&entry, gtk4_layer_shell::KeyboardMode::OnDemand);
+            entry.grab_focus();
+        });
+    }
+    entry.add_controller(click);
+
+    let focus = gtk4::EventControllerFocus::new();
+    {
+        let entry = entry.clone();
+        focus.connect_leave(move |_| {
+            set_entry_keyboard(&entry, gtk4_layer_shell::KeyboardMode::None);
+        });
+    }
+    entry.add_controller(focus);
+
+    let signals = signals.clone();
+    let id      = notif.id;
+    entry.connect_activate(move |entry| {
+        let text = entry.text().trim().to_string();
+        if text.is_empty() { return; }
+        let _ = signals.send(ServerSignal::Replied { id, text });
+        entry.set_text("");
+        set_entry_keyboard(entry, gtk4_layer_shell::KeyboardMode::None);
+        on_invoked();
+    });
+
+    entry
+}
+
+fn build_action_buttons(
+    notif:      &Notification,
+    signals:    &UnboundedSender<ServerSignal>,
+    on_invoked: Rc<dyn Fn()>,
+) -> Vec<gtk4::Widget> {
+    notif
+        .actions
+        .iter()
+        .filter(|(key, _)| key != "default")
+        .map(|(key, label)| {
+            if key == "inline-reply" {
+                return build_reply_entry(notif, label, signals, Rc::clone(&on_invoked)).upcast();
+            }
+
+            let btn = Button::builder()
+                .label(label.as_str())
+                .css_classes(["notiActionBtn"])
+                .valign(gtk4::Align::Center)
+                .build();
+            btn.set_cursor_from_name(Some("pointer"));
+
+            let signals    = signals.clone();
+            let on_invoked = Rc::clone(&on_invoked);
+            let key        = key.clone();
+            let id         = notif.id;
+            btn.connect_clicked(move |_| {
+                invoke_action(&signals, id, &key);
+                on_invoked();
+            });
+            btn.upcast()
+        })
+        .collect()
+}
+
+// ─── body markup ──────────────────────────────────────────────────────────────
+
+fn is_markup_entity(s: &str) -> bool {
+    let Some(end) = s.find(';') else { return false };
+    let name = &s[1..end];
+    match name.strip_prefix('#') {
+        Some(num) => {
+            let code = match num.strip_prefix('x').or_else(|| num.strip_prefix('X')) {
+                Some(hex) => u32::from_str_radix(hex, 16),
+                None      => num.parse::<u32>(),
+            };
+            code.ok().and_then(char::from_u32).is_some_and(is_markup_char)
+        }
+        None => matches!(name, "amp" | "lt" | "gt" | "quot" | "apos"),
+    }
+}
+
+/// Characters GMarkup accepts in a character reference (the XML `Char` production).
+fn is_markup_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r') || (!c.is_control() && !matches!(c, '\u{FFFE}' | '\u{FFFF}'))
+}
+
+fn tag_attribute(tag: &str, attr: &str) -> Option<String> {
+    let start = tag.find(&format!("{attr}="))? + attr.len() + 1;
+    let rest  = &tag[start..];
+    let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let value = &rest[1..];
+    let end   = value.find(quote)?;
+    Some(value[..end].replace("&amp;", "&"))
+}
+
+/// Converts the spec's body markup (`b`, `i`, `u`, `a href`) into balanced Pango
+/// markup. Everything else is escaped or dropped, keeping the text content.
+fn body_to_markup(body: &str) -> String {
+    let mut out   = String::with_capacity(body.len());
+    let mut stack: Vec<&'static str> = Vec::new();
+    let mut rest  = body;
+
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '<' => {
+                let starts_tag = rest[1..]
+                    .trim_start_matches('/')
+                    .starts_with(|c: char| c.is_ascii_alphabetic());
+                let end = rest.find('>').filter(|_| starts_tag);
+                let Some(end) = end else {
+                    out.push_str("&lt;");
+                    rest = &rest[1..];
+                    continue;
+                };
+                let tag = &rest[1..end];
+                rest = &rest[end + 1..];
+
+                let closing = tag.starts_with('/');
+                let name = tag
+                    .trim_start_matches('/')
+                    .split(|c: char| c.is_whitespace() || c == '/')
+                    .next()
+                    .unwrap_or("")
+                    .to_ascii_lowercase();
+                let name: &'static str = match name.as_str() {
+                    "b" => "b",
+                    "i" => "i",
+                    "u" => "u",
+                    "a" => "a",
+                    "br" => { out.push('\n'); continue; }
+                    _ => continue,
+                };
+
+                if closing {
+                    if let Some(pos) = stack.iter().rposition(|t| *t == name) {
+                        for open in stack.drain(pos..).rev() {
+                            out.push_str(&format!("</{open}>"));
+                        }
+                    }
+                } else if name == "a" {
+                    // Pango rejects nested links, so an inner one keeps only its text.
+                    if stack.contains(&"a") { continue; }
+                    let href = tag_attribute(tag, "href").filter(|h| {
+                        h.starts_with("http://") || h.starts_with("https://") || h.starts_with("mailto:")
+                    });
+                    if let Some(href) = href {
+                        out.push_str(&format!("<a href=\"{}\">", glib::markup_escape_text(&href)));
+                        stack.push("a");
+                    }
+                } else if !tag.ends_with('/') {
+                    out.push_str(&format!("<{name}>"));
+                    stack.push(name);
+                }
+            }
+            '>' => {
+                out.push_str("&gt;");
+                rest = &rest[1..];
+            }
+            '&' => {
+                if is_markup_entity(rest) {
+                    let end = rest.find(';').unwrap_or(0);
+                    out.push_str(&rest[..=end]);
+                    rest = &rest[end + 1..];
+                } else {
+                    out.push_str("&amp;");
+                    rest = &rest[1..];
+                }
+            }
+            _ => {
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    for open in stack.into_iter().rev() {
+        out.push_str(&format!("</{open}>"));
+    }
+    out
+}
+
+#[derive(Clone)]
+struct NotificationRow {
+    icon:       Image,
+    summary:    Label,
+    body:       Label,
+    popup_body: Label,
+    actions:    GtkBox,
+    progress:   GtkBox,
+    age:        Label,
+    popup_age:  Label,
+    timestamp:  Rc<Cell<std::time::SystemTime>>,
+    on_invoked: Rc<dyn Fn()>,
+    dismiss:    Rc<dyn Fn(CloseReason)>,
+    held:       Rc<Cell<bool>>,
+    closed:     Rc<Cell<bool>>,
+    container:  GtkBox,
+    group:      Rc<RefCell<String>>,
+}
+
+fn notification_texture(image: &NotificationImage) -> Option<gtk4::gdk::Texture> {
+    match image {
+        NotificationImage::Path(path) => {
+            let file = if path.starts_with("file://") {
+                gtk4::gio::File::for_uri(path)
+            } else {
+                gtk4::gio::File::for_path(path)
+            };
+            gtk4::gdk::Texture::from_file(&file).ok()
+        }
+        NotificationImage::Data { width, height, rowstride, has_alpha, data } => {
+            let format = if *has_alpha {
+                gtk4::gdk::MemoryFormat::R8g8b8a8
+            } else {
+                gtk4::gdk::MemoryFormat::R8g8b8
+            };
+            let bytes = glib::Bytes::from(data.as_slice());
+            Some(
+                gtk4::gdk::MemoryTexture::new(*width, *height, format, &bytes, *rowstride as usize)
+                    .upcast(),
+            )
+        }
+    }
+}
+
+/// Shows the notification's image if it has one, otherwise its themed icon.
+fn set_notification_image(img: &Image, notif: &Notification) -> bool {
+    if let Some(texture) = notif.image.as_ref().and_then(notification_texture) {
+        img.set_paintable(Some(&texture));
+        return true;
+    }
+    set_notification_icon(img, &notif.icon)
+}
+
+fn set_notification_icon(img: &Image, icon: &str) -> bool {
+    let display = gtk4::gdk::Display::default().expect("Failed to get default GDK display");
+    let icon_theme = gtk4::IconTheme::for_display(&display);
+    if icon_theme.has_icon(icon) {
+        img.set_icon_name(Some(icon));
+        true
+    } else {
+        false
+    }
+}
+
+// ─── timestamps ───────────────────────────────────────────────────────────────
+
+const AGE_REFRESH_SECS: u32 = 30;
+
+fn relative_age(timestamp: std::time::SystemTime) -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(timestamp)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    match secs {
+        0..60           => "now".to_string(),
+        60..3600        => format!("{}m", secs / 60),
+        3600..86400     => format!("{}h", secs / 3600),
+        86400..604800   => format!("{}d", secs / 86400),
+        _               => format!("{}w", secs / 604800),
+    }
+}
+
+fn refresh_row_age(row: &NotificationRow) {
+    let age = relative_age(row.timestamp.get());
+    row.age.set_text(&age);
+    row.popup_age.set_text(&age);
+}
+
+// ─── progress ─────────────────────────────────────────────────────────────────
+
+const ROW_PROGRESS_WIDTH:   i32 = 80;
+pub const BADGE_PROGRESS_WIDTH: i32 = 220;
+
+/// Builds the track for the `value` hint; the fill is sized like the osd bar.
+pub fn build_progress_bar(width: i32) -> GtkBox {
+    let fill = GtkBox::new(gtk4::Orientation::Horizontal, 0);
+    fill.set_css_classes(&["osd-fill-box", "notiProgressFill"]);
+    fill.set_halign(gtk4::Align::Start);
+
+    let track = GtkBox::new(gtk4::Orientation::Horizontal, 0);
+    track.set_css_classes(&["notiProgress"]);
+    track.set_width_request(width);
+    track.set_valign(gtk4::Align::Center);
+    track.set_halign(gtk4::Align::Start);
+    track.set_visible(false);
+    track.append(&fill);
+    track
+}
+
+fn set_progress(track: &GtkBox, value: Option<u8>) {
+    let Some(fill) = track.first_child() else { return };
+    match value {
+        Some(value) => {
+            let total = track.width_request();
+            let width = ((total as f64) * (value as f64 / 100.0)) as i32;
+            fill.set_width_request(width.max(4));
+            track.set_tooltip_text(Some(&format!("{}%", value)));
+            track.set_visible(true);
+        }
+        None => track.set_visible(false),
+    }
+}
+
+fn update_notification_row(
+    row:     &NotificationRow,
+    notif:   &Notification,
+    signals: &UnboundedSender<ServerSignal>,
+) {
+    row.summary.set_text(&notif.summary);
+    row.body.set_markup(&body_to_markup(&notif.body));
+    row.popup_body.set_markup(&body_to_markup(&notif.body));
+    set_notification_image(&row.icon, notif);
+    set_progress(&row.progress, notif.value);
+    row.timestamp.set(notif.timestamp);
+    row.closed.set(false);
+    refresh_row_age(row);
+
+    while let Some(child) = row.actions.first_child() {
+        row.actions.remove(&child);
+    }
+    for btn in build_action_buttons(notif, signals, Rc::clone(&row.on_invoked)) {
+        row.actions.append(&btn);
+    }
+}
+
+/// Times a notification out once `delay` passes, unless it was replaced since.
+/// Transient ones go away entirely; the rest stay in the dock and history, but
+/// the client is still told it expired so it doesn't wait on it forever.
+fn expire_row_after(
+    row:     &NotificationRow,
+    notif:   &Notification,
+    delay:   Duration,
+    signals: &UnboundedSender<ServerSignal>,
+) {
+    let row       = row.clone();
+    let signals   = signals.clone();
+    let id        = notif.id;
+    let shown_at  = notif.timestamp;
+    let transient = notif.transient;
+    glib::timeout_add_local_once(delay, move || {
+        if row.timestamp.get() != shown_at { return; }
+        if transient {
+            (row.dismiss)(CloseReason::Expired);
+        } else if !row.closed.replace(true) {
+            let _ = signals.send(ServerSignal::Closed { id, reason: CloseReason::Expired });
+        }
+    });
+}
+
+const PROBE_PATH: &str = "/var/lib/cynager/info.probe";
+
+pub fn probe_set_value(key: &str) -> Option<String> {
+    let file = File::open(PROBE_PATH).ok()?;
     let reader = io::BufReader::new(file);
     let mut in_set_block = false;
-    let mut dnd = String::new();
 
     for line in reader.lines().map_while(Result::ok) {
         let trimmed = line.trim().to_string();
@@ -129,363 +824,2009 @@ fn play_notification_sound() {
             in_set_block = false;
             continue;
         }
-        if in_set_block && trimmed.starts_with("dnd") {
-            let parts: Vec<&str> = trimmed.split(':').collect();
-            if parts.len() >= 2 {
-                dnd = parts[1].trim().to_string();
-            }
+        if in_set_block
+            && let Some((k, v)) = trimmed.split_once(':')
+            && k.trim() == key
+        {
+            return Some(v.trim().to_string());
         }
     }
+    None
+}
 
-    if dnd == "false" {
-        thread::spawn(|| {
-            let (_stream, stream_handle) = match OutputStream::try_default() {
-                Ok(v) => v,
-                Err(_) => return,
-            };
-            let sink = match Sink::try_new(&stream_handle) {
-                Ok(s) => s,
-                Err(_) => return,
-            };
-            if let Ok(file) = File::open("/var/lib/cynager/niri/sound/notiv/notiv.mp3") {
-                if let Ok(source) = Decoder::new(BufReader::new(file)) {
-                    sink.append(source);
-                    sink.sleep_until_end();
-                }
+/// Rewrites `key: value` inside the probe's `:set` block, adding the line if missing.
+fn write_probe_set_value(key: &str, value: &str) -> io::Result<()> {
+    // Only a missing probe may start from scratch; anything unreadable is left alone.
+    let content = match std::fs::read_to_string(PROBE_PATH) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    let mut out: Vec<String> = Vec::new();
+    let mut in_set_block = false;
+    let mut written = false;
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed == ":set" {
+            in_set_block = true;
+        } else if trimmed == ":end" && in_set_block {
+            if !written {
+                out.push(format!("{key}: {value}"));
+                written = true;
             }
-        });
+            in_set_block = false;
+        } else if in_set_block
+            && !written
+            && let Some((k, _)) = trimmed.split_once(':')
+            && k.trim() == key
+        {
+            let indent = &line[..line.len() - line.trim_start().len()];
+            out.push(format!("{indent}{key}: {value}"));
+            written = true;
+            continue;
+        }
+        out.push(line.to_string());
     }
+
+    if !written {
+        out.push(":set".to_string());
+        out.push(format!("{key}: {value}"));
+        out.push(":end".to_string());
+    }
+
+    write_atomic(Path::new(PROBE_PATH), &(out.join("\n") + "\n"))
 }
 
-pub fn connect_notifications_to_dock(
-    mut rx: UnboundedReceiver<Notification>,
-    noti_window: &GtkBox,
-    main_window: &ApplicationWindow,
-    app_img: &Image,
-    cos_btn: &Button,
-    badge: &Label,
-    badge_head: &Label,
-    noti_all: &GtkBox,
-) { 
-    
-    let history: Rc<RefCell<VecDeque<Notification>>> =
-        Rc::new(RefCell::new(VecDeque::with_capacity(50)));
+// ─── do not disturb ───────────────────────────────────────────────────────────
 
-    let pending_count: Rc<Cell<u32>>  = Rc::new(Cell::new(0));
-    let is_expanded:   Rc<Cell<bool>> = Rc::new(Cell::new(false));
-    let current_width: Rc<Cell<f64>>  = Rc::new(Cell::new(300.0));
-    let ctx = gtk4::glib::MainContext::default();
-    ctx.spawn_local(clone!(
-        #[strong] noti_window,
-        #[strong] main_window,
-        #[strong] app_img,
-        #[strong] cos_btn,
-        #[strong] badge,
-        #[strong] badge_head,
-        #[strong] noti_all,
-        async move {
-            while let Some(notif) = rx.recv().await {
-                {
-                    let mut h = history.borrow_mut();
-                    if h.len() == 50 { h.pop_front(); }
-                    h.push_back(notif.clone());
-                }
+pub fn dnd_enabled() -> bool {
+    probe_set_value("dnd").map(|v| v == "true").unwrap_or(false)
+}
 
-                let notification_icon = Image::from_file("/var/lib/cynager/icons/noti.svg");
-                notification_icon.set_icon_size(gtk4::IconSize::Normal);
-                notification_icon.set_css_classes(&["notiIcon"]);
-                notification_icon.set_height_request(28);
+pub fn set_dnd(enabled: bool) {
+    if let Err(e) = write_probe_set_value("dnd", if enabled { "true" } else { "false" }) {
+        eprintln!("[notifications] failed to write dnd state: {e}");
+    }
+}
 
-                let display = gtk4::gdk::Display::default().expect("Failed to get default GDK display");
-                let icon_theme = gtk4::IconTheme::for_display(&display);
+fn parse_clock(s: &str) -> Option<u32> {
+    let (h, m) = s.trim().split_once(':')?;
+    let (h, m): (u32, u32) = (h.parse().ok()?, m.parse().ok()?);
+    (h < 24 && m < 60).then_some(h * 60 + m)
+}
 
-                if icon_theme.has_icon(&notif.icon) {
-                    app_img.set_icon_name(Some(&notif.icon));
-                    notification_icon.set_icon_name(Some(&notif.icon));
-                } else {
-                    app_img.set_from_file(Some("/var/lib/cynager/icons/noti.svg"));
-                }
+/// `dnd_schedule: 22:00-07:00, 12:30-13:00` — windows may wrap past midnight.
+pub fn dnd_scheduled() -> bool {
+    use chrono::Timelike;
 
-                cos_btn.set_css_classes(&["spinning-coin", "cosIcon"]);
-                badge.set_visible(true);
-                badge_head.set_visible(true);
-                badge.set_ellipsize(gtk4::pango::EllipsizeMode::End);
-                badge_head.set_text(&format!("{}",notif.summary));
-                badge.set_text(&format!("{}", notif.body));
-
-                play_notification_sound();
-
-                let noti_label_sum = Label::new(Some(&notif.summary));
-                noti_label_sum.set_css_classes(&["notificationAllLabelSummary"]);
-                noti_label_sum.set_halign(gtk4::Align::Start);
-
-                let noti_label_bod = Label::new(Some(&notif.body));
-                noti_label_bod.set_css_classes(&["notificationAllLabelBody"]);
-                noti_label_bod.set_halign(gtk4::Align::Start);
-                noti_label_bod.set_valign(gtk4::Align::Center);
-                noti_label_bod.set_wrap(false);
-                noti_label_bod.set_single_line_mode(true);
-                noti_label_bod.set_width_request(100);
-                noti_label_bod.set_ellipsize(gtk4::pango::EllipsizeMode::End);
-
-                let noti_label_all = GtkBox::new(gtk4::Orientation::Horizontal, 20);
-                noti_label_all.append(&noti_label_sum);
-                noti_label_all.append(&noti_label_bod);
-
-                let noti_all_box = GtkBox::new(gtk4::Orientation::Horizontal, 5);
-                noti_all_box.set_css_classes(&["notificationAll"]);
-                noti_all_box.set_width_request(500);
-                noti_all_box.set_height_request(30);
-                noti_all_box.set_hexpand(true);
-                noti_all_box.set_margin_start(10);
-                noti_all_box.set_margin_end(10);
-                noti_all_box.set_vexpand(false);
-                noti_all_box.set_valign(gtk4::Align::End);
-                noti_all_box.set_halign(gtk4::Align::Center);
-
-                let pop_label = Label::new(Some(&notif.body));
-                pop_label.set_css_classes(&["notificationAllLabelBody"]);
-                pop_label.set_wrap(true);
-                pop_label.set_wrap_mode(gtk4::pango::WrapMode::WordChar);
-                pop_label.set_max_width_chars(45);
-                pop_label.set_halign(gtk4::Align::Start);
-
-                let popover = gtk4::Popover::new();
-                popover.popdown();
-                popover.set_child(Some(&pop_label));
-                popover.set_parent(&noti_all_box);
-                popover.set_css_classes(&["notiPopup"]);
-                popover.set_has_arrow(false);
-                popover.set_autohide(false);
-                popover.set_width_request(-1);
-                popover.set_vexpand(true);
-                popover.set_position(gtk4::PositionType::Bottom);
-
-                let hover_ctrl = gtk4::EventControllerMotion::new();
-                let pending: Rc<Cell<bool>> = Rc::new(Cell::new(false));
- 
-                let pending_enter = Rc::clone(&pending);
-                let popover_enter = popover.clone();
-                // let noti_all_box_enter = noti_all_box.clone();
-                hover_ctrl.connect_enter(move |_, _, _| {
-                    pending_enter.set(true);
-                    let pop = popover_enter.clone();
-                    pop.set_width_request(400);
-                    let pending_timeout = Rc::clone(&pending_enter);
-                    // let box_clone = noti_all_box_enter.clone();
-                    glib::timeout_add_local(Duration::from_millis(500), move || {
-                        if pending_timeout.get() {
-                            // pop.set_width_request(box_clone.width());
-                            pop.popup();
-                            pop.add_css_class("popupanim");
-                        }
-                        glib::ControlFlow::Break
-                    });
-                });
- 
-                let pending_leave = Rc::clone(&pending);
-                let popover_leave = popover.clone();
-                hover_ctrl.connect_leave(move |_| {
-                    pending_leave.set(false);
-                    popover_leave.popdown();
-                    popover_leave.remove_css_class("starting");
-                });
-
-                noti_all_box.add_controller(hover_ctrl);
-
-                let delete_btn = Button::new();
-                let delete = Image::from_file("/var/lib/cynager/icons/close.svg");
-                delete.set_icon_size(gtk4::IconSize::Normal);
-                delete_btn.set_child(Some(&delete));
-                delete_btn.set_css_classes(&["deleteBtn"]);
-                delete_btn.set_width_request(28);
-                delete_btn.set_height_request(28);
-                delete_btn.set_hexpand(true);
-                delete_btn.set_halign(gtk4::Align::End);
-                delete_btn.set_cursor_from_name(Some("pointer"));
-
-                noti_all_box.append(&notification_icon);
-                noti_all_box.append(&noti_label_all);
-                noti_all_box.append(&Label::builder()
-                    .label(&notif.app_name)
-                    .css_classes(["appName"])
-                    .hexpand(true)
-                    .halign(gtk4::Align::End)
-                    .build()
-                );
-                noti_all_box.append(&delete_btn);
-
-
-                let clear_all_btn = Button::new();
-                let clear = Image::from_file("/var/lib/cynager/icons/delete.svg");
-
-                clear.set_icon_size(gtk4::IconSize::Large);
-                clear_all_btn.set_child(Some(&clear));
-                clear_all_btn.set_css_classes(&["clearBtn"]);
-                clear_all_btn.set_width_request(30);
-                clear_all_btn.set_height_request(30);
-                clear_all_btn.set_hexpand(true);
-                clear_all_btn.set_halign(gtk4::Align::Fill);
-                clear_all_btn.set_vexpand(true);
-                clear_all_btn.set_valign(gtk4::Align::Center);
-                clear_all_btn.set_cursor_from_name(Some("pointer"));
-
-                let noti_all_clone = noti_all.clone();
-                clear_all_btn.connect_clicked( move |_| {
-                    noti_all_clone.add_css_class("vanish");
-                    let noti_all_clone = noti_all_clone.clone();
-                    glib::timeout_add_local(Duration::from_secs(1), move || {
-                        while let Some(child) = noti_all_clone.first_child() {
-                            noti_all_clone.remove(&child);
-                        }
-                        if let Some(root) = noti_all_clone.root() {
-                            if let Some(window) = root.downcast_ref::<gtk4::Window>() {
-                                window.set_visible(false);
-                                window.set_visible(true);
-                            }
-                        }
-                        noti_all_clone.remove_css_class("vanish");
-                        noti_all_clone.set_height_request(10);
-                        glib::ControlFlow::Break
-                    });                 
-                });
-
-                if noti_all.first_child().is_none() {
-                    noti_all.append(&clear_all_btn);
-                }
+    let Some(schedule) = probe_set_value("dnd_schedule") else { return false };
+    let now = chrono::Local::now();
+    in_schedule(&schedule, now.hour() * 60 + now.minute())
+}
 
-                noti_all.prepend(&noti_all_box);
-
-                let noti_all_clone = noti_all.clone();
-                delete_btn.connect_clicked( move |_| {
-                    noti_all_clone.remove(&noti_all_box);
-
-                    let first = noti_all_clone.first_child();
-                    let last  = noti_all_clone.last_child();
-                    let is_only_clear_btn = first.is_some() && first == last;
- 
-                    if is_only_clear_btn {
-                        noti_all_clone.add_css_class("vanish");
-                        let noti_all_clone = noti_all_clone.clone();
-                        glib::timeout_add_local(Duration::from_secs(1), move || {
-                            if let Some(child) = noti_all_clone.first_child() {
-                                noti_all_clone.remove(&child);
-                            }
-                            if let Some(root) = noti_all_clone.root() {
-                                if let Some(window) = root.downcast_ref::<gtk4::Window>() {
-                                    window.set_visible(false);
-                                    window.set_visible(true);
-                                }
-                            }
-                            noti_all_clone.remove_css_class("vanish");
-                            glib::ControlFlow::Break
-                        });
-                    }
-                });
-
-
-
-                pending_count.set(pending_count.get() + 1);
-
-                let display  = gtk4::gdk::Display::default().expect("no display");
-                let monitors = display.monitors();
-
-                if let Some(monitor) = monitors.item(0).and_downcast::<gtk4::gdk::Monitor>() {
-                    let geometry     = monitor.geometry();
-                    let target_width = (geometry.width() as f64 * 0.8) as i32;
-                    let start_width  = 300i32;
-                    let increment_per_frame =
-                        (target_width - start_width) as f64 / (1500.0 / (1000.0 / 114.0));
-
-                    if !is_expanded.get() {
-                        is_expanded.set(true);
-                        current_width.set(start_width as f64);
-
-                        // main_window.set_width_request(target_width + 50);
-                        noti_window.set_width_request(start_width);
-                        noti_window.set_css_classes(&["timeCapsule"]);
-                        main_window.set_layer(gtk4_layer_shell::Layer::Overlay);
-
-                        let noti_window_anim   = noti_window.clone();
-                        let current_width_anim = Rc::clone(&current_width);
-                        let main_c = main_window.clone();
-
-                        gtk4::glib::timeout_add_local(
-                            std::time::Duration::from_millis(6),
-                            move || {
-                                let next_w = current_width_anim.get() + increment_per_frame;
-                                if next_w >= target_width as f64 {
-                                    current_width_anim.set(target_width as f64);
-                                    noti_window_anim.set_width_request(target_width);
-                                    main_c.set_width_request(current_width_anim.get() as i32 + 30);
-                                    noti_window_anim.set_css_classes(&["blip", "timeCapsule"]);
-                                    return gtk4::glib::ControlFlow::Break;
-                                }
-                                current_width_anim.set(next_w);
-                                noti_window_anim.set_width_request(next_w as i32);
-                                gtk4::glib::ControlFlow::Continue
-                            },
-                        );
-                    } else {
-                        noti_window.remove_css_class("blip");
-                        let noti_window_blip = noti_window.clone();
-                        let main_c = main_window.clone();
-                        let current_width_anim = Rc::clone(&current_width);
-                        gtk4::glib::timeout_add_local(
-                            std::time::Duration::from_millis(6),
-                            move || {
-                                noti_window_blip.add_css_class("blip");
-                                main_c.set_width_request(current_width_anim.get() as i32 + 30);
-                                gtk4::glib::ControlFlow::Break
-                            },
-                        );
-                    }
+/// Whether `minute` (minutes past midnight) falls in any window of `schedule`;
+/// malformed windows never match.
+fn in_schedule(schedule: &str, minute: u32) -> bool {
+    schedule.split(',').any(|window| {
+        let Some((start, end)) = window.split_once('-') else { return false };
+        let (Some(start), Some(end)) = (parse_clock(start), parse_clock(end)) else { return false };
+        if start <= end {
+            (start..end).contains(&minute)
+        } else {
+            minute >= start || minute < end
+        }
+    })
+}
 
-                    let pending_count_hide = Rc::clone(&pending_count);
-                    let is_expanded_hide   = Rc::clone(&is_expanded);
-                    let current_width_hide = Rc::clone(&current_width);
-                    let noti_window_hide   = noti_window.clone();
-                    let main_window_hide   = main_window.clone();
-                    let app_img_hide       = app_img.clone();
-                    let cos_btn_hide       = cos_btn.clone();
-                    let badge_hide         = badge.clone();
-                    let badgeh_hide         = badge_head.clone();
-
-                    glib::timeout_add_local(std::time::Duration::from_millis(10000), move || {
-                        let remaining = pending_count_hide.get().saturating_sub(1);
-                        pending_count_hide.set(remaining);
-
-                        if remaining == 0 {
-                            badgeh_hide.set_text("");
-                            badge_hide.set_text("");
-                            app_img_hide.set_from_file(Some("/var/lib/cynager/icons/cos.svg"));
-                            cos_btn_hide.remove_css_class("spinning-coin");
-                            is_expanded_hide.set(false);
-
-                            let noti_window_c   = noti_window_hide.clone();
-                            let current_width_c = Rc::clone(&current_width_hide);
-                            let main_c          = main_window_hide.clone();
-
-                            glib::timeout_add_local(
-                                std::time::Duration::from_millis(6),
-                                move || {
-                                    let next_w = current_width_c.get() - increment_per_frame;
-                                    if next_w <= start_width as f64 {
-                                        noti_window_c.set_width_request(start_width);
-                                        noti_window_c.remove_css_class("blip");
-                                        main_c.set_layer(gtk4_layer_shell::Layer::Top);
-                                        return glib::ControlFlow::Break;
-                                    }
-                                    current_width_c.set(next_w);
-                                    noti_window_c.set_width_request(next_w as i32);
-                                    glib::ControlFlow::Continue
-                                },
-                            );
-                        }
-                        glib::ControlFlow::Break
-                    });
-                }
-            }
+pub fn dnd_active() -> bool {
+    dnd_enabled() || dnd_scheduled()
+}
+
+// ─── history persistence ──────────────────────────────────────────────────────
+
+const DEFAULT_HISTORY_CAP: usize = 50;
+
+fn history_path() -> std::path::PathBuf {
+    match std::env::var("XDG_STATE_HOME") {
+        Ok(dir) if !dir.is_empty() => std::path::Path::new(&dir).join("capsule/notifications.dat"),
+        _ => std::path::PathBuf::from("/var/lib/cynager/notifications.dat"),
+    }
+}
+
+/// `noti_history` caps the whole history, `noti_history_app` caps each app (0 = no per-app cap).
+fn history_limits() -> (usize, usize) {
+    let cap = probe_set_value("noti_history")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_HISTORY_CAP);
+    let per_app = probe_set_value("noti_history_app")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    (cap, per_app)
+}
+
+fn escape_field(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
+
+fn unescape_field(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
         }
-    ));
-}
\ No newline at end of file
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Writes through a sibling temp file and renames it over `path`, so a crash
+/// mid-write leaves the previous contents intact.
+fn write_atomic(path: &Path, content: &str) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    std::fs::write(&tmp, content)?;
+    if let Ok(meta) = std::fs::metadata(path) {
+        std::fs::set_permissions(&tmp, meta.permissions())?;
+    }
+    std::fs::rename(&tmp, path)
+}
+
+const HISTORY_SAVE_DELAY_MS: u64 = 500;
+
+thread_local! {
+    static HISTORY_SAVE_PENDING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Coalesces saves so bursts of updates (progress ticks, replaces) cost one write.
+fn schedule_history_save(history: &Rc<RefCell<VecDeque<Notification>>>) {
+    if HISTORY_SAVE_PENDING.with(|p| p.replace(true)) {
+        return;
+    }
+    let history = Rc::clone(history);
+    glib::timeout_add_local_once(Duration::from_millis(HISTORY_SAVE_DELAY_MS), move || {
+        HISTORY_SAVE_PENDING.with(|p| p.set(false));
+        save_history(&history.borrow());
+    });
+}
+
+fn save_history(history: &VecDeque<Notification>) {
+    let path = history_path();
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+
+    let content = history
+        .iter()
+        .filter(|n| !n.transient)
+        .map(|n| {
+            let secs = n
+                .timestamp
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            let urgency = match n.urgency {
+                Urgency::Low      => 0,
+                Urgency::Normal   => 1,
+                Urgency::Critical => 2,
+            };
+            let image = match &n.image {
+                Some(NotificationImage::Path(path)) => path.as_str(),
+                _ => "",
+            };
+            format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                n.id,
+                secs,
+                urgency,
+                escape_field(&n.app_name),
+                escape_field(&n.summary),
+                escape_field(&n.body),
+                escape_field(&n.icon),
+                escape_field(image),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    if let Err(e) = write_atomic(&path, &content) {
+        eprintln!("[notifications] failed to save history to {}: {e}", path.display());
+    }
+}
+
+fn load_history() -> VecDeque<Notification> {
+    let Ok(content) = std::fs::read_to_string(history_path()) else { return VecDeque::new() };
+
+    let mut history: VecDeque<Notification> = content
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [id, secs, urgency, app_name, summary, body, icon, ref rest @ ..] = fields[..] else { return None };
+            let image = rest.first().map(|p| unescape_field(p)).filter(|p| !p.is_empty());
+            let saved = std::time::UNIX_EPOCH + Duration::from_secs(secs.parse().ok()?);
+            Some(Notification {
+                id: id.parse().ok()?,
+                app_name: unescape_field(app_name),
+                summary: unescape_field(summary),
+                body: unescape_field(body),
+                icon: unescape_field(icon),
+                image: image.map(NotificationImage::Path),
+                timestamp: saved,
+                actions: Vec::new(),
+                resident: false,
+                transient: false,
+                urgency: match urgency {
+                    "0" => Urgency::Low,
+                    "2" => Urgency::Critical,
+                    _ => Urgency::Normal,
+                },
+                category: None,
+                expire_timeout: -1,
+                sound_file: None,
+                sound_name: None,
+                suppress_sound: false,
+                value: None,
+            })
+        })
+        .collect();
+
+    prune_history(&mut history);
+    history
+}
+
+/// Drops entries past the history limits and returns their ids.
+fn prune_history(history: &mut VecDeque<Notification>) -> Vec<u32> {
+    let (cap, per_app) = history_limits();
+    let mut pruned = Vec::new();
+
+    if per_app > 0 {
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        let mut keep = vec![true; history.len()];
+        for (i, n) in history.iter().enumerate().rev() {
+            let count = seen.entry(n.app_name.clone()).or_insert(0);
+            *count += 1;
+            if *count > per_app { keep[i] = false; }
+        }
+        let mut keep = keep.into_iter();
+        history.retain(|n| {
+            let kept = keep.next().unwrap_or(true);
+            if !kept { pruned.push(n.id); }
+            kept
+        });
+    }
+
+    while history.len() > cap {
+        if let Some(n) = history.pop_front() {
+            pruned.push(n.id);
+        }
+    }
+    pruned
+}
+
+/// Records `notif` and returns the ids that fell out of history to make room.
+fn record_history(history: &Rc<RefCell<VecDeque<Notification>>>, notif: &Notification) -> Vec<u32> {
+    let mut h = history.borrow_mut();
+    let pruned = if let Some(entry) = h.iter_mut().find(|n| n.id == notif.id) {
+        *entry = notif.clone();
+        Vec::new()
+    } else {
+        h.push_back(notif.clone());
+        prune_history(&mut h)
+    };
+    drop(h);
+    schedule_history_save(history);
+    pruned
+}
+
+// ─── per-app rules ────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Default)]
+struct RuleActions {
+    drop:     bool,
+    mute:     bool,
+    no_popup: bool,
+    urgency:  Option<Urgency>,
+    sound:    Option<String>,
+}
+
+enum RuleMatch {
+    App(String),
+    Summary(Regex),
+    Category(String),
+}
+
+struct NotificationRule {
+    matcher: RuleMatch,
+    actions: RuleActions,
+}
+
+fn parse_rule(line: &str) -> Option<NotificationRule> {
+    let line = line.strip_prefix(':').unwrap_or(line);
+    let (kind, rest) = line.split_once(char::is_whitespace)?;
+    let rest = rest.trim().strip_prefix('"')?;
+    let (pattern, rest) = rest.split_once('"')?;
+    let actions_str = rest.trim().strip_prefix(':')?;
+
+    let matcher = match kind {
+        "app"      => RuleMatch::App(pattern.to_lowercase()),
+        "summary"  => RuleMatch::Summary(Regex::new(pattern).ok()?),
+        "category" => RuleMatch::Category(pattern.to_string()),
+        _ => return None,
+    };
+
+    let mut actions = RuleActions::default();
+    for action in actions_str.split(',').map(str::trim) {
+        match action {
+            "drop"    => actions.drop = true,
+            "mute"    => actions.mute = true,
+            "nopopup" => actions.no_popup = true,
+            "urgency low"      => actions.urgency = Some(Urgency::Low),
+            "urgency normal"   => actions.urgency = Some(Urgency::Normal),
+            "urgency critical" => actions.urgency = Some(Urgency::Critical),
+            _ => {
+                if let Some(sound) = action.strip_prefix("sound ") {
+                    actions.sound = Some(sound.trim().trim_matches('"').to_string());
+                }
+            }
+        }
+    }
+
+    Some(NotificationRule { matcher, actions })
+}
+
+/// Reads the `notifications { ... }` block of the probe's `:set` section, e.g.
+///
+/// ```text
+/// notifications {
+///     app "ci-bot" : mute, nopopup
+///     summary "^Backup (started|finished)" : drop
+///     category "im.received" : urgency critical
+///     app "discord" : sound /var/lib/cynager/niri/sound/notiv/ping.ogg
+/// }
+/// ```
+fn parse_rules(content: &str) -> Vec<NotificationRule> {
+    let mut in_set   = false;
+    let mut in_block = false;
+    let mut rules    = Vec::new();
+
+    for line in content.lines().map(str::trim) {
+        match line {
+            ":set" => { in_set = true; continue; }
+            ":end" => { in_set = false; in_block = false; continue; }
+            _ => {}
+        }
+        if !in_set {
+            continue;
+        }
+        if !in_block {
+            in_block = line.strip_prefix("notifications").map(str::trim) == Some("{");
+            continue;
+        }
+        if line.starts_with('}') {
+            break;
+        }
+        if !line.is_empty()
+            && let Some(rule) = parse_rule(line)
+        {
+            rules.push(rule);
+        }
+    }
+    rules
+}
+
+thread_local! {
+    static RULES: RefCell<Option<(std::time::SystemTime, Rc<Vec<NotificationRule>>)>> = const { RefCell::new(None) };
+}
+
+/// Parsed rules, re-read only when the probe's mtime changes.
+fn load_rules() -> Rc<Vec<NotificationRule>> {
+    let Some(mtime) = std::fs::metadata(PROBE_PATH).and_then(|m| m.modified()).ok() else {
+        return Rc::new(Vec::new());
+    };
+    RULES.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if let Some((cached_at, rules)) = cache.as_ref()
+            && *cached_at == mtime
+        {
+            return Rc::clone(rules);
+        }
+        let Ok(content) = std::fs::read_to_string(PROBE_PATH) else {
+            return Rc::new(Vec::new());
+        };
+        let rules = Rc::new(parse_rules(&content));
+        *cache = Some((mtime, Rc::clone(&rules)));
+        rules
+    })
+}
+
+/// Flags add up across every matching rule; for urgency and sound the first
+/// match wins, so specific rules belong above general ones.
+fn apply_rules(rules: &[NotificationRule], notif: &Notification) -> RuleActions {
+    let mut result = RuleActions::default();
+    for rule in rules {
+        let matched = match &rule.matcher {
+            RuleMatch::App(name)      => notif.app_name.to_lowercase() == *name,
+            RuleMatch::Summary(re)    => re.is_match(&notif.summary),
+            RuleMatch::Category(cat)  => notif.category.as_deref() == Some(cat.as_str()),
+        };
+        if !matched { continue; }
+        result.drop     |= rule.actions.drop;
+        result.mute     |= rule.actions.mute;
+        result.no_popup |= rule.actions.no_popup;
+        if result.urgency.is_none() {
+            result.urgency = rule.actions.urgency;
+        }
+        if result.sound.is_none() {
+            result.sound = rule.actions.sound.clone();
+        }
+    }
+    result
+}
+
+// ─── sounds ───────────────────────────────────────────────────────────────────
+
+const DEFAULT_SOUND: &str = "/var/lib/cynager/niri/sound/notiv/notiv.mp3";
+const SOUND_EXTENSIONS: [&str; 5] = ["oga", "ogg", "wav", "flac", "mp3"];
+
+type CachedSound = Buffered<Decoder<BufReader<File>>>;
+
+/// A resolved sound; only sounds from the theme or the user's own config are
+/// cached, since client `sound-file` paths are arbitrary and unbounded.
+struct NotificationSound {
+    path:      PathBuf,
+    cacheable: bool,
+}
+
+static SOUND_PLAYER: OnceLock<std::sync::mpsc::Sender<NotificationSound>> = OnceLock::new();
+
+fn sound_theme_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Ok(data_home) = std::env::var("XDG_DATA_HOME") {
+        dirs.push(PathBuf::from(data_home).join("sounds"));
+    } else if let Ok(home) = std::env::var("HOME") {
+        dirs.push(PathBuf::from(home).join(".local/share/sounds"));
+    }
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .unwrap_or_else(|_| "/usr/local/share:/usr/share".to_string());
+    for dir in data_dirs.split(':').filter(|d| !d.is_empty()) {
+        dirs.push(PathBuf::from(dir).join("sounds"));
+    }
+    dirs.push(PathBuf::from("/var/lib/cynager/niri/sound"));
+    dirs
+}
+
+/// Looks a sound-name up in the configured `sound_theme`, falling back to the
+/// freedesktop theme, per the XDG sound theme layout (`<theme>/stereo/<name>.oga`).
+fn find_themed_sound(name: &str) -> Option<PathBuf> {
+    // sound-name comes from the client; keep it to a single path component.
+    if name.is_empty() || name.contains('/') || name.contains("..") {
+        return None;
+    }
+
+    let mut themes = Vec::new();
+    if let Some(theme) = probe_set_value("sound_theme").filter(|t| !t.is_empty()) {
+        themes.push(theme);
+    }
+    themes.push("freedesktop".to_string());
+
+    let dirs = sound_theme_dirs();
+    for theme in &themes {
+        for dir in &dirs {
+            for sub in ["stereo", ""] {
+                let base = dir.join(theme).join(sub);
+                for ext in SOUND_EXTENSIONS {
+                    let candidate = base.join(format!("{}.{}", name, ext));
+                    if candidate.is_file() {
+                        return Some(candidate);
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Picks the sound for a notification, most specific first: the `sound-file`
+/// hint, a per-app rule, the `sound-name` hint, the per-urgency probe keys
+/// (`sound_low`, `sound_normal`, `sound_critical`), then the default chime.
+fn resolve_notification_sound(notif: &Notification, rule: &RuleActions) -> Option<NotificationSound> {
+    if notif.suppress_sound || rule.mute {
+        return None;
+    }
+
+    if let Some(path) = notif.sound_file.as_deref().and_then(existing_sound) {
+        return Some(NotificationSound { path, cacheable: false });
+    }
+    if let Some(path) = rule.sound.as_deref().and_then(existing_sound) {
+        return Some(NotificationSound { path, cacheable: true });
+    }
+    if let Some(path) = notif.sound_name.as_deref().and_then(find_themed_sound) {
+        return Some(NotificationSound { path, cacheable: true });
+    }
+
+    let urgency_key = match notif.urgency {
+        Urgency::Low      => "sound_low",
+        Urgency::Normal   => "sound_normal",
+        Urgency::Critical => "sound_critical",
+    };
+    if let Some(value) = probe_set_value(urgency_key) {
+        if value == "none" {
+            return None;
+        }
+        if let Some(path) = existing_sound(&value).or_else(|| find_themed_sound(&value)) {
+            return Some(NotificationSound { path, cacheable: true });
+        }
+    }
+
+    existing_sound(DEFAULT_SOUND).map(|path| NotificationSound { path, cacheable: true })
+}
+
+fn existing_sound(p: &str) -> Option<PathBuf> {
+    let path = PathBuf::from(p.strip_prefix("file://").unwrap_or(p));
+    path.is_file().then_some(path)
+}
+
+fn decode_sound(path: &Path) -> Option<CachedSound> {
+    let file = File::open(path).ok()?;
+    match Decoder::new(BufReader::new(file)) {
+        Ok(source) => Some(source.buffered()),
+        Err(e) => {
+            eprintln!("[notifications] cannot decode {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Starts the audio thread once. It keeps a single output stream open and
+/// caches decoded theme and configured sounds, so bursts of notifications just
+/// mix clones of the buffered samples into that stream.
+fn sound_player() -> &'static std::sync::mpsc::Sender<NotificationSound> {
+    SOUND_PLAYER.get_or_init(|| {
+        let (tx, rx) = std::sync::mpsc::channel::<NotificationSound>();
+        thread::spawn(move || {
+            let mut output: Option<(OutputStream, rodio::OutputStreamHandle)> = None;
+            let mut cache: HashMap<PathBuf, CachedSound> = HashMap::new();
+
+            while let Ok(NotificationSound { path, cacheable }) = rx.recv() {
+                if output.is_none() {
+                    match OutputStream::try_default() {
+                        Ok(v) => output = Some(v),
+                        Err(e) => {
+                            eprintln!("[notifications] no audio output: {}", e);
+                            continue;
+                        }
+                    }
+                }
+
+                let sound = match cache.get(&path) {
+                    Some(sound) => sound.clone(),
+                    None => {
+                        let Some(sound) = decode_sound(&path) else { continue };
+                        if cacheable {
+                            cache.insert(path, sound.clone());
+                        }
+                        sound
+                    }
+                };
+
+                let (_, handle) = output.as_ref().unwrap();
+                if handle.play_raw(sound.convert_samples()).is_err() {
+                    // The device went away; reopen it on the next notification.
+                    output = None;
+                }
+            }
+        });
+        tx
+    })
+}
+
+fn play_notification_sound(sound: NotificationSound) {
+    let _ = sound_player().send(sound);
+}
+
+const CAPSULE_START_WIDTH: i32 = 300;
+const DEFAULT_EXPIRE_MS:   u64 = 10000;
+
+#[derive(Clone)]
+struct TimeCapsule {
+    noti_window:   GtkBox,
+    main_window:   ApplicationWindow,
+    app_img:       Image,
+    cos_btn:       Button,
+    badge:         Label,
+    badge_head:    Label,
+    badge_actions: GtkBox,
+    badge_progress: GtkBox,
+    pending_count: Rc<Cell<u32>>,
+    is_expanded:   Rc<Cell<bool>>,
+    current_width: Rc<Cell<f64>>,
+    home_monitor:  Rc<RefCell<CapsuleHome>>,
+}
+
+/// Where the time capsule goes back to after following the focused output.
+enum CapsuleHome {
+    /// It is on its own monitor.
+    Home,
+    /// It moved; restore this layer-shell pin (`None` = the compositor's choice).
+    Away(Option<gtk4::gdk::Monitor>),
+}
+
+fn capsule_increment(target_width: i32) -> f64 {
+    (target_width - CAPSULE_START_WIDTH) as f64 / (1500.0 / (1000.0 / 114.0))
+}
+
+/// The monitor the time capsule is actually on: its layer-shell pin if set,
+/// otherwise wherever the compositor mapped it.
+fn capsule_monitor(window: &ApplicationWindow) -> Option<gtk4::gdk::Monitor> {
+    if let Some(monitor) = window.monitor() {
+        return Some(monitor);
+    }
+    let display = gtk4::gdk::Display::default()?;
+    if let Some(monitor) = window.surface().and_then(|s| display.monitor_at_surface(&s)) {
+        return Some(monitor);
+    }
+    display.monitors().item(0).and_downcast::<gtk4::gdk::Monitor>()
+}
+
+/// Name of niri's focused output, kept current by an event-stream thread so
+/// expanding the capsule never waits on IPC.
+fn focused_output_name() -> Option<String> {
+    static FOCUSED: OnceLock<Arc<Mutex<Option<String>>>> = OnceLock::new();
+    let focused = FOCUSED.get_or_init(|| {
+        let focused = Arc::new(Mutex::new(None));
+        let writer  = Arc::clone(&focused);
+        thread::spawn(move || follow_focused_output(writer));
+        focused
+    });
+    focused.lock().ok()?.clone()
+}
+
+fn follow_focused_output(focused: Arc<Mutex<Option<String>>>) {
+    let mut sock = match niri_ipc::socket::Socket::connect() {
+        Ok(sock) => sock,
+        Err(e) => {
+            eprintln!("[notifications] cannot reach niri: {e}");
+            return;
+        }
+    };
+    if !matches!(sock.send(niri_ipc::Request::EventStream), Ok(Ok(_))) {
+        eprintln!("[notifications] niri refused the event stream");
+        return;
+    }
+
+    let mut read_event = sock.read_events();
+    let mut outputs: HashMap<u64, Option<String>> = HashMap::new();
+    while let Ok(event) = read_event() {
+        let output = match event {
+            niri_ipc::Event::WorkspacesChanged { workspaces } => {
+                outputs = workspaces.iter().map(|w| (w.id, w.output.clone())).collect();
+                workspaces.into_iter().find(|w| w.is_focused).and_then(|w| w.output)
+            }
+            niri_ipc::Event::WorkspaceActivated { id, focused: true } => {
+                outputs.get(&id).cloned().flatten()
+            }
+            _ => continue,
+        };
+        if let Some(output) = output
+            && let Ok(mut current) = focused.lock()
+        {
+            *current = Some(output);
+        }
+    }
+}
+
+fn focused_output_monitor() -> Option<gtk4::gdk::Monitor> {
+    let name = focused_output_name()?;
+    crate::resolve_monitor(&gtk4::gdk::Display::default()?, &name)
+}
+
+/// With `notify_output: focused` in the probe, the capsule hops to the focused
+/// niri output while it is expanded and goes back home on release.
+fn notification_monitor(capsule: &TimeCapsule) -> Option<gtk4::gdk::Monitor> {
+    let current = capsule_monitor(&capsule.main_window);
+    if probe_set_value("notify_output").as_deref() != Some("focused") {
+        return current;
+    }
+    let Some(focused) = focused_output_monitor() else { return current };
+    if current.as_ref() != Some(&focused) {
+        let mut home = capsule.home_monitor.borrow_mut();
+        if matches!(*home, CapsuleHome::Home) {
+            *home = CapsuleHome::Away(capsule.main_window.monitor());
+        }
+        capsule.main_window.set_monitor(Some(&focused));
+    }
+    Some(focused)
+}
+
+fn expand_time_capsule(capsule: &TimeCapsule) {
+    capsule.pending_count.set(capsule.pending_count.get() + 1);
+
+    let monitor = if capsule.is_expanded.get() {
+        capsule_monitor(&capsule.main_window)
+    } else {
+        notification_monitor(capsule)
+    };
+    let Some(monitor) = monitor else { return };
+
+    let geometry     = monitor.geometry();
+    let target_width = (geometry.width() as f64 * 0.8) as i32;
+    let start_width  = CAPSULE_START_WIDTH;
+    let increment_per_frame = capsule_increment(target_width);
+
+    if !capsule.is_expanded.get() {
+        capsule.is_expanded.set(true);
+        capsule.current_width.set(start_width as f64);
+
+        // main_window.set_width_request(target_width + 50);
+        capsule.noti_window.set_width_request(start_width);
+        capsule.noti_window.set_css_classes(&["timeCapsule"]);
+        capsule.main_window.set_layer(gtk4_layer_shell::Layer::Overlay);
+
+        let noti_window_anim   = capsule.noti_window.clone();
+        let current_width_anim = Rc::clone(&capsule.current_width);
+        let main_c = capsule.main_window.clone();
+
+        gtk4::glib::timeout_add_local(
+            std::time::Duration::from_millis(6),
+            move || {
+                let next_w = current_width_anim.get() + increment_per_frame;
+                if next_w >= target_width as f64 {
+                    current_width_anim.set(target_width as f64);
+                    noti_window_anim.set_width_request(target_width);
+                    main_c.set_width_request(current_width_anim.get() as i32 + 30);
+                    noti_window_anim.set_css_classes(&["blip", "timeCapsule"]);
+                    return gtk4::glib::ControlFlow::Break;
+                }
+                current_width_anim.set(next_w);
+                noti_window_anim.set_width_request(next_w as i32);
+                gtk4::glib::ControlFlow::Continue
+            },
+        );
+    } else {
+        capsule.noti_window.remove_css_class("blip");
+        let noti_window_blip = capsule.noti_window.clone();
+        let main_c = capsule.main_window.clone();
+        let current_width_anim = Rc::clone(&capsule.current_width);
+        gtk4::glib::timeout_add_local(
+            std::time::Duration::from_millis(6),
+            move || {
+                noti_window_blip.add_css_class("blip");
+                main_c.set_width_request(current_width_anim.get() as i32 + 30);
+                gtk4::glib::ControlFlow::Break
+            },
+        );
+    }
+}
+
+fn release_time_capsule(capsule: &TimeCapsule) {
+    let remaining = capsule.pending_count.get().saturating_sub(1);
+    capsule.pending_count.set(remaining);
+    if remaining != 0 { return; }
+
+    capsule.badge_head.set_text("");
+    capsule.badge.set_text("");
+    while let Some(child) = capsule.badge_actions.first_child() {
+        capsule.badge_actions.remove(&child);
+    }
+    capsule.badge_actions.set_visible(false);
+    set_progress(&capsule.badge_progress, None);
+    capsule.app_img.set_from_file(Some("/var/lib/cynager/icons/cos.svg"));
+    capsule.cos_btn.remove_css_class("spinning-coin");
+    capsule.is_expanded.set(false);
+
+    let start_width         = CAPSULE_START_WIDTH;
+    let increment_per_frame = capsule_increment(capsule.current_width.get() as i32);
+    let noti_window_c   = capsule.noti_window.clone();
+    let current_width_c = Rc::clone(&capsule.current_width);
+    let main_c          = capsule.main_window.clone();
+    let home_monitor    = Rc::clone(&capsule.home_monitor);
+    let is_expanded     = Rc::clone(&capsule.is_expanded);
+
+    glib::timeout_add_local(
+        std::time::Duration::from_millis(6),
+        move || {
+            let next_w = current_width_c.get() - increment_per_frame;
+            if next_w <= start_width as f64 || increment_per_frame <= 0.0 {
+                noti_window_c.set_width_request(start_width);
+                noti_window_c.remove_css_class("blip");
+                main_c.set_layer(gtk4_layer_shell::Layer::Top);
+                if !is_expanded.get()
+                    && let CapsuleHome::Away(pin) = home_monitor.replace(CapsuleHome::Home)
+                {
+                    main_c.set_monitor(pin.as_ref());
+                }
+                return glib::ControlFlow::Break;
+            }
+            current_width_c.set(next_w);
+            noti_window_c.set_width_request(next_w as i32);
+            glib::ControlFlow::Continue
+        },
+    );
+}
+
+#[derive(Clone)]
+struct Dock {
+    noti_all: GtkBox,
+    rows:     Rc<RefCell<HashMap<u32, NotificationRow>>>,
+    groups:   Rc<RefCell<HashMap<String, NotificationGroup>>>,
+    history:  Rc<RefCell<VecDeque<Notification>>>,
+    signals:  UnboundedSender<ServerSignal>,
+    capsule:  TimeCapsule,
+    /// Set while the notification center is open so it follows history changes.
+    on_history_changed: HistoryListener,
+}
+
+type HistoryListener = Rc<RefCell<Option<Rc<dyn Fn()>>>>;
+
+fn history_changed(dock: &Dock) {
+    let listener = dock.on_history_changed.borrow().clone();
+    if let Some(listener) = listener {
+        listener();
+    }
+}
+
+#[derive(Clone)]
+struct NotificationGroup {
+    container: GtkBox,
+    header:    GtkBox,
+    rows:      GtkBox,
+    count:     Label,
+    toggle:    Button,
+    expanded:  Rc<Cell<bool>>,
+}
+
+fn refresh_group(group: &NotificationGroup) {
+    let expanded = group.expanded.get();
+    let mut count = 0;
+    let mut child = group.rows.first_child();
+    while let Some(row) = child {
+        row.set_visible(count == 0 || expanded);
+        count += 1;
+        child = row.next_sibling();
+    }
+    group.count.set_text(&count.to_string());
+    group.header.set_visible(count > 1);
+    group.toggle.set_label(if expanded { "▾" } else { "▸" });
+}
+
+fn group_for(dock: &Dock, app_name: &str) -> NotificationGroup {
+    if let Some(group) = dock.groups.borrow().get(app_name) {
+        return group.clone();
+    }
+
+    let count = Label::builder()
+        .css_classes(["notiGroupCount"])
+        .valign(gtk4::Align::Center)
+        .build();
+
+    let toggle = Button::builder()
+        .css_classes(["notiGroupBtn"])
+        .valign(gtk4::Align::Center)
+        .build();
+    toggle.set_cursor_from_name(Some("pointer"));
+
+    let dismiss_group = Button::new();
+    let dismiss_img = Image::from_file("/var/lib/cynager/icons/close.svg");
+    dismiss_img.set_icon_size(gtk4::IconSize::Normal);
+    dismiss_group.set_child(Some(&dismiss_img));
+    dismiss_group.set_css_classes(&["deleteBtn"]);
+    dismiss_group.set_tooltip_text(Some("Dismiss group"));
+    dismiss_group.set_cursor_from_name(Some("pointer"));
+
+    let header = GtkBox::new(gtk4::Orientation::Horizontal, 8);
+    header.set_css_classes(&["notiGroupHeader"]);
+    header.set_margin_start(20);
+    header.set_margin_end(20);
+    header.append(&Label::builder()
+        .label(app_name)
+        .css_classes(["notiGroupName"])
+        .hexpand(true)
+        .halign(gtk4::Align::Start)
+        .build()
+    );
+    header.append(&count);
+    header.append(&toggle);
+    header.append(&dismiss_group);
+
+    let rows = GtkBox::new(gtk4::Orientation::Vertical, 5);
+
+    let container = GtkBox::new(gtk4::Orientation::Vertical, 5);
+    container.set_css_classes(&["notiGroup"]);
+    container.set_valign(gtk4::Align::End);
+    container.append(&header);
+    container.append(&rows);
+
+    let group = NotificationGroup {
+        container,
+        header,
+        rows,
+        count,
+        toggle,
+        expanded: Rc::new(Cell::new(false)),
+    };
+
+    {
+        let group_c = group.clone();
+        group.toggle.connect_clicked(move |_| {
+            group_c.expanded.set(!group_c.expanded.get());
+            refresh_group(&group_c);
+        });
+    }
+
+    {
+        let dock_c = dock.clone();
+        let key    = app_name.to_string();
+        dismiss_group.connect_clicked(move |_| {
+            let members: Vec<NotificationRow> = dock_c
+                .rows
+                .borrow()
+                .values()
+                .filter(|row| *row.group.borrow() == key)
+                .cloned()
+                .collect();
+            for row in members {
+                (row.dismiss)(CloseReason::Dismissed);
+            }
+        });
+    }
+
+    dock.groups.borrow_mut().insert(app_name.to_string(), group.clone());
+    group
+}
+
+fn remove_from_group(dock: &Dock, key: &str, row_box: &GtkBox) {
+    let Some(group) = dock.groups.borrow().get(key).cloned() else { return };
+    if row_box.parent().as_ref() == Some(group.rows.upcast_ref()) {
+        group.rows.remove(row_box);
+    }
+    if group.rows.first_child().is_none() {
+        if group.container.parent().is_some() {
+            dock.noti_all.remove(&group.container);
+        }
+        dock.groups.borrow_mut().remove(key);
+    } else {
+        refresh_group(&group);
+    }
+}
+
+/// Puts `row_box` at the top of its app's group and that group at the top of the dock.
+fn place_in_group(dock: &Dock, key: &str, row_box: &GtkBox) {
+    let group = group_for(dock, key);
+    group.rows.prepend(row_box);
+    if group.container.parent().is_none() {
+        dock.noti_all.prepend(&group.container);
+    } else {
+        dock.noti_all.reorder_child_after(&group.container, None::<&gtk4::Widget>);
+    }
+    refresh_group(&group);
+}
+
+/// Moves a replaced row over when its new `app_name` belongs to another group.
+fn regroup_row(dock: &Dock, row: &NotificationRow, app_name: &str) {
+    if *row.group.borrow() == app_name { return; }
+    let old = row.group.replace(app_name.to_string());
+    remove_from_group(dock, &old, &row.container);
+    place_in_group(dock, app_name, &row.container);
+}
+
+fn add_notification_row(dock: &Dock, notif: &Notification) -> NotificationRow {
+    let notification_icon = Image::from_file("/var/lib/cynager/icons/noti.svg");
+    notification_icon.set_icon_size(gtk4::IconSize::Normal);
+    notification_icon.set_css_classes(&["notiIcon"]);
+    notification_icon.set_height_request(28);
+
+    set_notification_image(&notification_icon, notif);
+
+    let noti_label_sum = Label::new(Some(&notif.summary));
+    noti_label_sum.set_css_classes(&["notificationAllLabelSummary"]);
+    noti_label_sum.set_halign(gtk4::Align::Start);
+
+    let noti_label_bod = Label::new(None);
+    noti_label_bod.set_markup(&body_to_markup(&notif.body));
+    noti_label_bod.set_css_classes(&["notificationAllLabelBody"]);
+    noti_label_bod.set_halign(gtk4::Align::Start);
+    noti_label_bod.set_valign(gtk4::Align::Center);
+    noti_label_bod.set_wrap(false);
+    noti_label_bod.set_single_line_mode(true);
+    noti_label_bod.set_width_request(100);
+    noti_label_bod.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+
+    let noti_label_all = GtkBox::new(gtk4::Orientation::Horizontal, 20);
+    noti_label_all.append(&noti_label_sum);
+    noti_label_all.append(&noti_label_bod);
+
+    let noti_actions = GtkBox::new(gtk4::Orientation::Horizontal, 5);
+    noti_actions.set_css_classes(&["notiActions"]);
+    noti_actions.set_valign(gtk4::Align::Center);
+
+    let noti_progress = build_progress_bar(ROW_PROGRESS_WIDTH);
+    set_progress(&noti_progress, notif.value);
+
+    let noti_all_box = GtkBox::new(gtk4::Orientation::Horizontal, 5);
+    noti_all_box.set_css_classes(&["notificationAll"]);
+    noti_all_box.set_width_request(500);
+    noti_all_box.set_height_request(30);
+    noti_all_box.set_hexpand(true);
+    noti_all_box.set_margin_start(10);
+    noti_all_box.set_margin_end(10);
+    noti_all_box.set_vexpand(false);
+    noti_all_box.set_valign(gtk4::Align::End);
+    noti_all_box.set_halign(gtk4::Align::Center);
+    if notif.urgency == Urgency::Critical {
+        noti_all_box.add_css_class("notiCritical");
+    }
+
+    let pop_label = Label::new(None);
+    pop_label.set_markup(&body_to_markup(&notif.body));
+    pop_label.set_css_classes(&["notificationAllLabelBody"]);
+    pop_label.set_wrap(true);
+    pop_label.set_wrap_mode(gtk4::pango::WrapMode::WordChar);
+    pop_label.set_max_width_chars(45);
+    pop_label.set_halign(gtk4::Align::Start);
+
+    let pop_age = Label::new(Some(&relative_age(notif.timestamp)));
+    pop_age.set_css_classes(&["notiAge"]);
+    pop_age.set_halign(gtk4::Align::Start);
+
+    let pop_content = GtkBox::new(gtk4::Orientation::Vertical, 4);
+    pop_content.append(&pop_age);
+    pop_content.append(&pop_label);
+
+    let popover = gtk4::Popover::new();
+    popover.popdown();
+    popover.set_child(Some(&pop_content));
+    popover.set_parent(&noti_all_box);
+    popover.set_css_classes(&["notiPopup"]);
+    popover.set_has_arrow(false);
+    popover.set_autohide(false);
+    popover.set_width_request(-1);
+    popover.set_vexpand(true);
+    popover.set_position(gtk4::PositionType::Bottom);
+
+    let hover_ctrl = gtk4::EventControllerMotion::new();
+    let pending: Rc<Cell<bool>> = Rc::new(Cell::new(false));
+
+    let pending_enter = Rc::clone(&pending);
+    let popover_enter = popover.clone();
+    // let noti_all_box_enter = noti_all_box.clone();
+    hover_ctrl.connect_enter(move |_, _, _| {
+        pending_enter.set(true);
+        let pop = popover_enter.clone();
+        pop.set_width_request(400);
+        let pending_timeout = Rc::clone(&pending_enter);
+        // let box_clone = noti_all_box_enter.clone();
+        glib::timeout_add_local(Duration::from_millis(500), move || {
+            if pending_timeout.get() {
+                // pop.set_width_request(box_clone.width());
+                pop.popup();
+                pop.add_css_class("popupanim");
+            }
+            glib::ControlFlow::Break
+        });
+    });
+
+    let pending_leave = Rc::clone(&pending);
+    let popover_leave = popover.clone();
+    hover_ctrl.connect_leave(move |_| {
+        pending_leave.set(false);
+        popover_leave.popdown();
+        popover_leave.remove_css_class("starting");
+    });
+
+    noti_all_box.add_controller(hover_ctrl);
+
+    let delete_btn = Button::new();
+    let delete = Image::from_file("/var/lib/cynager/icons/close.svg");
+    delete.set_icon_size(gtk4::IconSize::Normal);
+    delete_btn.set_child(Some(&delete));
+    delete_btn.set_css_classes(&["deleteBtn"]);
+    delete_btn.set_width_request(28);
+    delete_btn.set_height_request(28);
+    delete_btn.set_hexpand(true);
+    delete_btn.set_halign(gtk4::Align::End);
+    delete_btn.set_cursor_from_name(Some("pointer"));
+
+    noti_all_box.append(&notification_icon);
+    noti_all_box.append(&noti_label_all);
+    noti_all_box.append(&noti_progress);
+    noti_all_box.append(&noti_actions);
+    noti_all_box.append(&Label::builder()
+        .label(&notif.app_name)
+        .css_classes(["appName"])
+        .hexpand(true)
+        .halign(gtk4::Align::End)
+        .build()
+    );
+    let noti_age = Label::builder()
+        .label(relative_age(notif.timestamp))
+        .css_classes(["notiAge"])
+        .build();
+    noti_all_box.append(&noti_age);
+    noti_all_box.append(&delete_btn);
+
+
+    let clear_all_btn = Button::new();
+    let clear = Image::from_file("/var/lib/cynager/icons/delete.svg");
+
+    clear.set_icon_size(gtk4::IconSize::Large);
+    clear_all_btn.set_child(Some(&clear));
+    clear_all_btn.set_css_classes(&["clearBtn"]);
+    clear_all_btn.set_width_request(30);
+    clear_all_btn.set_height_request(30);
+    clear_all_btn.set_hexpand(true);
+    clear_all_btn.set_halign(gtk4::Align::Fill);
+    clear_all_btn.set_vexpand(true);
+    clear_all_btn.set_valign(gtk4::Align::Center);
+    clear_all_btn.set_cursor_from_name(Some("pointer"));
+
+    let noti_all_clone = dock.noti_all.clone();
+    let dock_clear     = dock.clone();
+    clear_all_btn.connect_clicked( move |_| {
+        let cleared: Vec<(u32, NotificationRow)> = dock_clear.rows.borrow_mut().drain().collect();
+        for (id, row) in cleared {
+            // Held rows only give back their hold on the time capsule through here.
+            if row.held.replace(false) {
+                release_time_capsule(&dock_clear.capsule);
+            }
+            if !row.closed.replace(true) {
+                let _ = dock_clear.signals.send(ServerSignal::Closed { id, reason: CloseReason::Dismissed });
+            }
+        }
+        dock_clear.groups.borrow_mut().clear();
+        dock_clear.history.borrow_mut().clear();
+        schedule_history_save(&dock_clear.history);
+        history_changed(&dock_clear);
+        noti_all_clone.add_css_class("vanish");
+        let noti_all_clone = noti_all_clone.clone();
+        glib::timeout_add_local(Duration::from_secs(1), move || {
+            while let Some(child) = noti_all_clone.first_child() {
+                noti_all_clone.remove(&child);
+            }
+            if let Some(root) = noti_all_clone.root() {
+                if let Some(window) = root.downcast_ref::<gtk4::Window>() {
+                    window.set_visible(false);
+                    window.set_visible(true);
+                }
+            }
+            noti_all_clone.remove_css_class("vanish");
+            noti_all_clone.set_height_request(10);
+            glib::ControlFlow::Break
+        });                 
+    });
+
+    if dock.noti_all.first_child().is_none() {
+        dock.noti_all.append(&clear_all_btn);
+    }
+
+    place_in_group(dock, &notif.app_name, &noti_all_box);
+
+    let noti_all_clone = dock.noti_all.clone();
+    let noti_all_box_c = noti_all_box.clone();
+    let group          = Rc::new(RefCell::new(notif.app_name.clone()));
+    let group_key      = Rc::clone(&group);
+    let dock_dismiss   = dock.clone();
+    let held           = Rc::new(Cell::new(false));
+    let held_dismiss   = Rc::clone(&held);
+    let closed         = Rc::new(Cell::new(false));
+    let closed_dismiss = Rc::clone(&closed);
+    let id = notif.id;
+    let dismiss: Rc<dyn Fn(CloseReason)> = Rc::new(move |reason| {
+        if dock_dismiss.rows.borrow_mut().remove(&id).is_none() { return; }
+        if held_dismiss.replace(false) {
+            release_time_capsule(&dock_dismiss.capsule);
+        }
+        dock_dismiss.history.borrow_mut().retain(|n| n.id != id);
+        schedule_history_save(&dock_dismiss.history);
+        history_changed(&dock_dismiss);
+        if !closed_dismiss.replace(true) {
+            let _ = dock_dismiss.signals.send(ServerSignal::Closed { id, reason });
+        }
+
+        if noti_all_box_c.parent().is_none() { return; }
+        remove_from_group(&dock_dismiss, &group_key.borrow(), &noti_all_box_c);
+
+        let first = noti_all_clone.first_child();
+        let last  = noti_all_clone.last_child();
+        let is_only_clear_btn = first.is_some() && first == last;
+
+        if is_only_clear_btn {
+            noti_all_clone.add_css_class("vanish");
+            let noti_all_clone = noti_all_clone.clone();
+            glib::timeout_add_local(Duration::from_secs(1), move || {
+                if let Some(child) = noti_all_clone.first_child() {
+                    noti_all_clone.remove(&child);
+                }
+                if let Some(root) = noti_all_clone.root() {
+                    if let Some(window) = root.downcast_ref::<gtk4::Window>() {
+                        window.set_visible(false);
+                        window.set_visible(true);
+                    }
+                }
+                noti_all_clone.remove_css_class("vanish");
+                glib::ControlFlow::Break
+            });
+        }
+    });
+
+    {
+        let dismiss = Rc::clone(&dismiss);
+        delete_btn.connect_clicked(move |_| dismiss(CloseReason::Dismissed));
+    }
+
+    let on_invoked: Rc<dyn Fn()> = {
+        let dismiss       = Rc::clone(&dismiss);
+        let badge_actions = dock.capsule.badge_actions.clone();
+        let resident      = notif.resident;
+        Rc::new(move || {
+            while let Some(child) = badge_actions.first_child() {
+                badge_actions.remove(&child);
+            }
+            if !resident { dismiss(CloseReason::Dismissed); }
+        })
+    };
+
+    for btn in build_action_buttons(notif, &dock.signals, Rc::clone(&on_invoked)) {
+        noti_actions.append(&btn);
+    }
+
+    if notif.actions.iter().any(|(key, _)| key == "default") {
+        let default_click = gtk4::GestureClick::new();
+        default_click.set_button(1);
+        let signals    = dock.signals.clone();
+        let on_invoked = Rc::clone(&on_invoked);
+        let id         = notif.id;
+        default_click.connect_released(move |gesture, _, _, _| {
+            gesture.set_state(gtk4::EventSequenceState::Claimed);
+            invoke_action(&signals, id, "default");
+            on_invoked();
+        });
+        noti_label_all.add_controller(default_click);
+        noti_label_all.set_cursor_from_name(Some("pointer"));
+    }
+
+    let row = NotificationRow {
+        icon:       notification_icon,
+        summary:    noti_label_sum,
+        body:       noti_label_bod,
+        popup_body: pop_label,
+        actions:    noti_actions,
+        progress:   noti_progress,
+        age:        noti_age,
+        popup_age:  pop_age,
+        timestamp:  Rc::new(Cell::new(notif.timestamp)),
+        on_invoked,
+        dismiss,
+        held,
+        closed,
+        container:  noti_all_box,
+        group,
+    };
+    dock.rows.borrow_mut().insert(notif.id, row.clone());
+    row
+}
+
+fn show_server_state(cos_btn: &Button, state: &ServerState) {
+    match state {
+        ServerState::Active => {
+            cos_btn.remove_css_class("notiInactive");
+            cos_btn.set_tooltip_text(None);
+        }
+        ServerState::Waiting { owner } => {
+            cos_btn.add_css_class("notiInactive");
+            cos_btn.set_tooltip_text(Some(&format!(
+                "Notifications are handled by {} — capsule takes over when it exits",
+                owner.as_deref().unwrap_or("another daemon"),
+            )));
+        }
+        ServerState::Unavailable(reason) => {
+            cos_btn.add_css_class("notiInactive");
+            cos_btn.set_tooltip_text(Some(&format!("Notification server unavailable: {reason}")));
+        }
+    }
+}
+
+// ─── notification center ──────────────────────────────────────────────────────
+
+#[derive(Clone)]
+struct NotificationCenter {
+    dock:       Dock,
+    list:       GtkBox,
+    filters:    GtkBox,
+    search:     gtk4::SearchEntry,
+    bulk:       Button,
+    app_filter: Rc<RefCell<Option<String>>>,
+}
+
+fn format_timestamp(timestamp: std::time::SystemTime) -> String {
+    let now  = chrono::Local::now();
+    let when = chrono::DateTime::<chrono::Local>::from(timestamp);
+    if when.date_naive() == now.date_naive() {
+        when.format("%H:%M").to_string()
+    } else {
+        when.format("%b %d, %H:%M").to_string()
+    }
+}
+
+/// The body as the user sees it, without tags or entities.
+fn body_text(body: &str) -> String {
+    match gtk4::pango::parse_markup(&body_to_markup(body), '\0') {
+        Ok((_, text, _)) => text.to_string(),
+        Err(_) => body.to_string(),
+    }
+}
+
+fn center_matches(center: &NotificationCenter, notif: &Notification) -> bool {
+    if let Some(app) = center.app_filter.borrow().as_ref()
+        && &notif.app_name != app
+    {
+        return false;
+    }
+    let query = center.search.text().to_lowercase();
+    let query = query.trim();
+    query.is_empty()
+        || notif.summary.to_lowercase().contains(query)
+        || body_text(&notif.body).to_lowercase().contains(query)
+}
+
+fn dismiss_notification(dock: &Dock, id: u32) {
+    let row = dock.rows.borrow().get(&id).cloned();
+    if let Some(row) = row {
+        (row.dismiss)(CloseReason::Dismissed);
+        return;
+    }
+    dock.history.borrow_mut().retain(|n| n.id != id);
+    schedule_history_save(&dock.history);
+    history_changed(dock);
+    let _ = dock.signals.send(ServerSignal::Closed { id, reason: CloseReason::Dismissed });
+}
+
+fn build_center_item(center: &NotificationCenter, notif: &Notification) -> GtkBox {
+    let icon = Image::from_file("/var/lib/cynager/icons/noti.svg");
+    icon.set_icon_size(gtk4::IconSize::Large);
+    icon.set_css_classes(&["notiIcon"]);
+    icon.set_valign(gtk4::Align::Start);
+    set_notification_image(&icon, notif);
+
+    let summary = Label::builder()
+        .label(notif.summary.as_str())
+        .css_classes(["notiCenterSummary"])
+        .halign(gtk4::Align::Start)
+        .hexpand(true)
+        .ellipsize(gtk4::pango::EllipsizeMode::End)
+        .build();
+    let meta = Label::builder()
+        .label(format!("{} · {}", notif.app_name, format_timestamp(notif.timestamp)))
+        .css_classes(["notiCenterMeta"])
+        .halign(gtk4::Align::End)
+        .build();
+
+    let head = GtkBox::new(gtk4::Orientation::Horizontal, 10);
+    head.append(&summary);
+    head.append(&meta);
+
+    let body = Label::new(None);
+    body.set_markup(&body_to_markup(&notif.body));
+    body.set_css_classes(&["notiCenterBody"]);
+    body.set_halign(gtk4::Align::Start);
+    body.set_wrap(true);
+    body.set_wrap_mode(gtk4::pango::WrapMode::WordChar);
+    body.set_xalign(0.0);
+    body.set_visible(!notif.body.is_empty());
+
+    let text = GtkBox::new(gtk4::Orientation::Vertical, 2);
+    text.set_hexpand(true);
+    text.append(&head);
+    text.append(&body);
+
+    let delete_btn = Button::new();
+    let delete = Image::from_file("/var/lib/cynager/icons/close.svg");
+    delete.set_icon_size(gtk4::IconSize::Normal);
+    delete_btn.set_child(Some(&delete));
+    delete_btn.set_css_classes(&["deleteBtn"]);
+    delete_btn.set_valign(gtk4::Align::Start);
+    delete_btn.set_cursor_from_name(Some("pointer"));
+    {
+        let center = center.clone();
+        let id     = notif.id;
+        delete_btn.connect_clicked(move |_| {
+            dismiss_notification(&center.dock, id);
+            fill_center(&center);
+        });
+    }
+
+    let item = GtkBox::new(gtk4::Orientation::Horizontal, 10);
+    item.set_css_classes(&["notiCenterItem"]);
+    if notif.urgency == Urgency::Critical {
+        item.add_css_class("notiCritical");
+    }
+    item.append(&icon);
+    item.append(&text);
+    item.append(&delete_btn);
+    item
+}
+
+fn fill_center(center: &NotificationCenter) {
+    let history: Vec<Notification> = center.dock.history.borrow().iter().rev().cloned().collect();
+
+    let mut apps: Vec<String> = Vec::new();
+    for notif in &history {
+        if !apps.contains(&notif.app_name) {
+            apps.push(notif.app_name.clone());
+        }
+    }
+    let stale = center.app_filter.borrow().as_ref().is_some_and(|app| !apps.contains(app));
+    if stale {
+        *center.app_filter.borrow_mut() = None;
+    }
+
+    while let Some(child) = center.filters.first_child() {
+        center.filters.remove(&child);
+    }
+    let active = center.app_filter.borrow().clone();
+    for app in std::iter::once(None).chain(apps.into_iter().map(Some)) {
+        let btn = Button::builder()
+            .label(app.as_deref().unwrap_or("All"))
+            .css_classes(["notiFilterBtn"])
+            .build();
+        btn.set_cursor_from_name(Some("pointer"));
+        if app == active {
+            btn.add_css_class("notiFilterActive");
+        }
+        center.filters.append(&btn);
+        let center = center.clone();
+        btn.connect_clicked(move |_| {
+            *center.app_filter.borrow_mut() = app.clone();
+            fill_center(&center);
+        });
+    }
+
+    while let Some(child) = center.list.first_child() {
+        center.list.remove(&child);
+    }
+    let mut shown = 0;
+    for notif in history.iter().filter(|n| center_matches(center, n)) {
+        center.list.append(&build_center_item(center, notif));
+        shown += 1;
+    }
+    if shown == 0 {
+        center.list.append(&Label::builder()
+            .label(if history.is_empty() { "No notifications" } else { "Nothing matches" })
+            .css_classes(["notiCenterEmpty"])
+            .build()
+        );
+    }
+
+    let filtered = active.is_some() || !center.search.text().trim().is_empty();
+    center.bulk.set_label(if filtered { "Dismiss shown" } else { "Clear all" });
+    center.bulk.set_sensitive(shown > 0);
+}
+
+fn spawn_notification_center(dock: &Dock, open: Rc<Cell<bool>>) {
+    let Some(app) = dock.capsule.main_window.application() else { return };
+    open.set(true);
+
+    let win = ApplicationWindow::builder()
+        .application(&app)
+        .title("capsuleNC")
+        .css_classes(["ctrlOverlay"])
+        .build();
+
+    win.init_layer_shell();
+    win.set_namespace(Some("NotificationCenter"));
+    win.set_layer(gtk4_layer_shell::Layer::Overlay);
+    win.set_keyboard_mode(gtk4_layer_shell::KeyboardMode::OnDemand);
+    win.remove_css_class("background");
+    win.set_anchor(gtk4_layer_shell::Edge::Top, true);
+    win.set_anchor(gtk4_layer_shell::Edge::Bottom, true);
+    win.set_anchor(gtk4_layer_shell::Edge::Left, true);
+    win.set_anchor(gtk4_layer_shell::Edge::Right, true);
+
+    let backdrop = Button::builder()
+        .css_classes(["ctrlBackdrop"])
+        .hexpand(true)
+        .vexpand(true)
+        .build();
+
+    let title = Label::builder()
+        .label("Notifications")
+        .css_classes(["notiCenterTitle"])
+        .halign(gtk4::Align::Start)
+        .hexpand(true)
+        .build();
+
+    let bulk = Button::builder()
+        .label("Clear all")
+        .css_classes(["notiActionBtn"])
+        .valign(gtk4::Align::Center)
+        .build();
+    bulk.set_cursor_from_name(Some("pointer"));
+
+    let header = GtkBox::new(gtk4::Orientation::Horizontal, 10);
+    header.append(&title);
+    header.append(&bulk);
+
+    let search = gtk4::SearchEntry::new();
+    search.set_placeholder_text(Some("Search notifications"));
+    search.set_css_classes(&["notiReplyEntry", "notiCenterSearch"]);
+
+    let filters = GtkBox::new(gtk4::Orientation::Horizontal, 5);
+    filters.set_css_classes(&["notiCenterFilters"]);
+    let filter_scroller = gtk4::ScrolledWindow::builder()
+        .hscrollbar_policy(gtk4::PolicyType::Automatic)
+        .vscrollbar_policy(gtk4::PolicyType::Never)
+        .child(&filters)
+        .build();
+
+    let list = GtkBox::new(gtk4::Orientation::Vertical, 6);
+    let list_scroller = gtk4::ScrolledWindow::builder()
+        .hscrollbar_policy(gtk4::PolicyType::Never)
+        .vscrollbar_policy(gtk4::PolicyType::Automatic)
+        .min_content_height(120)
+        .max_content_height(560)
+        .propagate_natural_height(true)
+        .child(&list)
+        .build();
+
+    let panel = GtkBox::new(gtk4::Orientation::Vertical, 10);
+    panel.set_css_classes(&["notiCenter"]);
+    panel.set_halign(gtk4::Align::Center);
+    panel.set_valign(gtk4::Align::Start);
+    panel.set_margin_top(80);
+    panel.set_width_request(600);
+    panel.append(&header);
+    panel.append(&search);
+    panel.append(&filter_scroller);
+    panel.append(&list_scroller);
+
+    let layout = gtk4::Overlay::new();
+    layout.set_child(Some(&backdrop));
+    layout.add_overlay(&panel);
+    win.set_child(Some(&layout));
+
+    let center = NotificationCenter {
+        dock:       dock.clone(),
+        list,
+        filters,
+        search:     search.clone(),
+        bulk:       bulk.clone(),
+        app_filter: Rc::new(RefCell::new(None)),
+    };
+    fill_center(&center);
+
+    {
+        // Dismissals from the dock can arrive in bursts; rebuild once per idle.
+        let center = center.clone();
+        let queued = Rc::new(Cell::new(false));
+        *dock.on_history_changed.borrow_mut() = Some(Rc::new(move || {
+            if queued.replace(true) { return; }
+            let center = center.clone();
+            let queued = Rc::clone(&queued);
+            glib::idle_add_local_once(move || {
+                queued.set(false);
+                fill_center(&center);
+            });
+        }));
+    }
+
+    {
+        let center = center.clone();
+        search.connect_search_changed(move |_| fill_center(&center));
+    }
+
+    {
+        let center = center.clone();
+        bulk.connect_clicked(move |_| {
+            let ids: Vec<u32> = center.dock.history
+                .borrow()
+                .iter()
+                .filter(|n| center_matches(&center, n))
+                .map(|n| n.id)
+                .collect();
+            for id in ids {
+                dismiss_notification(&center.dock, id);
+            }
+            fill_center(&center);
+        });
+    }
+
+    {
+        let win_c = win.clone();
+        backdrop.connect_clicked(move |_| win_c.close());
+    }
+
+    {
+        let win_c = win.clone();
+        let keys  = gtk4::EventControllerKey::new();
+        keys.connect_key_pressed(move |_, key, _, _| {
+            if key == gtk4::gdk::Key::Escape {
+                win_c.close();
+                return glib::Propagation::Stop;
+            }
+            glib::Propagation::Proceed
+        });
+        win.add_controller(keys);
+    }
+
+    {
+        let listener = Rc::clone(&dock.on_history_changed);
+        win.connect_close_request(move |_| {
+            listener.borrow_mut().take();
+            open.set(false);
+            glib::Propagation::Proceed
+        });
+    }
+
+    win.present();
+    search.grab_focus();
+}
+
+/// The widgets of the time capsule and the dock strip that notifications drive.
+pub struct DockWidgets {
+    pub noti_window:    GtkBox,
+    pub main_window:    ApplicationWindow,
+    pub app_img:        Image,
+    pub cos_btn:        Button,
+    pub badge:          Label,
+    pub badge_head:     Label,
+    pub badge_actions:  GtkBox,
+    pub badge_progress: GtkBox,
+    pub noti_all:       GtkBox,
+}
+
+pub fn connect_notifications_to_dock(
+    mut rx:  UnboundedReceiver<NotificationEvent>,
+    widgets: DockWidgets,
+    signals: UnboundedSender<ServerSignal>,
+) { 
+    let DockWidgets {
+        noti_window, main_window, app_img, cos_btn, badge, badge_head,
+        badge_actions, badge_progress, noti_all,
+    } = widgets;
+
+    let capsule = TimeCapsule {
+        noti_window:   noti_window.clone(),
+        main_window:   main_window.clone(),
+        app_img:       app_img.clone(),
+        cos_btn:       cos_btn.clone(),
+        badge:         badge.clone(),
+        badge_head:    badge_head.clone(),
+        badge_actions: badge_actions.clone(),
+        badge_progress: badge_progress.clone(),
+        pending_count: Rc::new(Cell::new(0)),
+        is_expanded:   Rc::new(Cell::new(false)),
+        current_width: Rc::new(Cell::new(CAPSULE_START_WIDTH as f64)),
+        home_monitor:  Rc::new(RefCell::new(CapsuleHome::Home)),
+    };
+
+    let dock = Dock {
+        noti_all: noti_all.clone(),
+        rows:     Rc::new(RefCell::new(HashMap::new())),
+        groups:   Rc::new(RefCell::new(HashMap::new())),
+        history:  Rc::new(RefCell::new(load_history())),
+        signals,
+        capsule,
+        on_history_changed: Rc::new(RefCell::new(None)),
+    };
+
+    if probe_set_value("notify_output").as_deref() == Some("focused") {
+        // Start following niri now so the first expansion already knows the output.
+        let _ = focused_output_name();
+    }
+
+    let restored: Vec<Notification> = dock.history.borrow().iter().cloned().collect();
+    for notif in &restored {
+        add_notification_row(&dock, notif);
+    }
+
+    {
+        let rows = Rc::clone(&dock.rows);
+        glib::timeout_add_seconds_local(AGE_REFRESH_SECS, move || {
+            for row in rows.borrow().values() {
+                refresh_row_age(row);
+            }
+            glib::ControlFlow::Continue
+        });
+    }
+
+    {
+        let dock        = dock.clone();
+        let center_open = Rc::new(Cell::new(false));
+        cos_btn.set_cursor_from_name(Some("pointer"));
+        cos_btn.connect_clicked(move |_| {
+            if center_open.get() { return; }
+            spawn_notification_center(&dock, Rc::clone(&center_open));
+        });
+    }
+
+    let ctx = gtk4::glib::MainContext::default();
+    ctx.spawn_local(clone!(
+        #[strong] app_img,
+        #[strong] cos_btn,
+        #[strong] badge,
+        #[strong] badge_head,
+        #[strong] badge_actions,
+        async move {
+            let capsule = dock.capsule.clone();
+            while let Some(event) = rx.recv().await {
+                let mut notif = match event {
+                    NotificationEvent::Notify(notif) => *notif,
+                    NotificationEvent::Close(id) => {
+                        let row = dock.rows.borrow().get(&id).cloned();
+                        if let Some(row) = row {
+                            (row.dismiss)(CloseReason::ClosedByCall);
+                        }
+                        continue;
+                    }
+                    NotificationEvent::State(state) => {
+                        show_server_state(&cos_btn, &state);
+                        continue;
+                    }
+                };
+
+                let rule = apply_rules(&load_rules(), &notif);
+                if rule.drop {
+                    // The user never saw it, so it wasn't dismissed by them.
+                    let _ = dock.signals.send(ServerSignal::Closed {
+                        id:     notif.id,
+                        reason: CloseReason::Undefined,
+                    });
+                    continue;
+                }
+                if let Some(urgency) = rule.urgency {
+                    notif.urgency = urgency;
+                }
+
+                // Whatever falls out of history goes from the dock too, so
+                // no row outlives its entry.
+                for id in record_history(&dock.history, &notif) {
+                    let row = dock.rows.borrow().get(&id).cloned();
+                    if let Some(row) = row {
+                        (row.dismiss)(CloseReason::Expired);
+                    }
+                }
+                history_changed(&dock);
+
+                let sticky = notif.urgency == Urgency::Critical || notif.expire_timeout == 0;
+                let delay  = if notif.expire_timeout > 0 {
+                    Duration::from_millis(notif.expire_timeout as u64)
+                } else {
+                    Duration::from_millis(DEFAULT_EXPIRE_MS)
+                };
+
+                let existing = dock.rows.borrow().get(&notif.id).cloned();
+                if let Some(row) = existing {
+                    update_notification_row(&row, &notif, &dock.signals);
+                    regroup_row(&dock, &row, &notif.app_name);
+                    if !sticky {
+                        expire_row_after(&row, &notif, delay, &dock.signals);
+                    }
+                    let silenced = dnd_active() && notif.urgency != Urgency::Critical;
+                    if capsule.is_expanded.get() && notif.urgency != Urgency::Low && !rule.no_popup && !silenced {
+                        badge_head.set_text(&notif.summary);
+                        badge.set_markup(&body_to_markup(&notif.body));
+                        set_progress(&capsule.badge_progress, notif.value);
+                        if !set_notification_image(&app_img, &notif) {
+                            app_img.set_from_file(Some("/var/lib/cynager/icons/noti.svg"));
+                        }
+                    }
+                    continue;
+                }
+
+                let row = add_notification_row(&dock, &notif);
+                if !sticky {
+                    expire_row_after(&row, &notif, delay, &dock.signals);
+                }
+
+                let silenced = dnd_active() && notif.urgency != Urgency::Critical;
+
+                if notif.urgency == Urgency::Low || rule.no_popup || silenced {
+                    continue;
+                }
+
+                if !set_notification_image(&app_img, &notif) {
+                    app_img.set_from_file(Some("/var/lib/cynager/icons/noti.svg"));
+                }
+
+                cos_btn.set_css_classes(&["spinning-coin", "cosIcon"]);
+                badge.set_visible(true);
+                badge_head.set_visible(true);
+                badge.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+                badge_head.set_text(&notif.summary);
+                badge.set_markup(&body_to_markup(&notif.body));
+                set_progress(&capsule.badge_progress, notif.value);
+
+                while let Some(child) = badge_actions.first_child() {
+                    badge_actions.remove(&child);
+                }
+                for btn in build_action_buttons(&notif, &dock.signals, Rc::clone(&row.on_invoked)) {
+                    badge_actions.append(&btn);
+                }
+                badge_actions.set_visible(badge_actions.first_child().is_some());
+
+                if let Some(sound) = resolve_notification_sound(&notif, &rule) {
+                    play_notification_sound(sound);
+                }
+
+                expand_time_capsule(&capsule);
+
+                if sticky {
+                    row.held.set(true);
+                } else {
+                    let capsule_hide = capsule.clone();
+                    glib::timeout_add_local_once(delay, move || {
+                        release_time_capsule(&capsule_hide);
+                    });
+                }
+            }
+        }
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notification(app_name: &str, summary: &str, category: Option<&str>) -> Notification {
+        Notification {
+            id:             1,
+            app_name:       app_name.to_string(),
+            summary:        summary.to_string(),
+            body:           String::new(),
+            icon:           String::new(),
+            image:          None,
+            timestamp:      std::time::SystemTime::UNIX_EPOCH,
+            actions:        Vec::new(),
+            resident:       false,
+            transient:      false,
+            urgency:        Urgency::Normal,
+            category:       category.map(str::to_string),
+            expire_timeout: -1,
+            sound_file:     None,
+            sound_name:     None,
+            suppress_sound: false,
+            value:          None,
+        }
+    }
+
+    fn rules(block: &str) -> Vec<NotificationRule> {
+        parse_rules(&format!(":set\ndnd=false\n{block}\n:end\n"))
+    }
+
+    #[test]
+    fn rules_skip_unknown_keys_and_actions() {
+        let rules = rules("notifications {\n    sender \"x\" : drop\n    app \"x\" : mute, explode\n}");
+        assert_eq!(rules.len(), 1);
+        assert!(matches!(&rules[0].matcher, RuleMatch::App(name) if name == "x"));
+        assert!(rules[0].actions.mute);
+        assert!(!rules[0].actions.drop);
+    }
+
+    #[test]
+    fn rules_need_an_opening_brace() {
+        assert!(rules("notifications\n    app \"x\" : drop\n}").is_empty());
+        assert!(rules("notifications {").is_empty());
+        // An unclosed block ends with the :set section.
+        let rules = parse_rules(":set\nnotifications {\napp \"x\" : drop\n:end\napp \"y\" : drop\n");
+        assert_eq!(rules.len(), 1);
+    }
+
+    #[test]
+    fn rules_keep_quoted_values_whole() {
+        let rules = rules(concat!(
+            "notifications {\n",
+            "    summary \"Build: (ok|failed)\" : nopopup\n",
+            "    app \"Some App\" : sound \"/tmp/a b.ogg\"\n",
+            "    app unquoted : drop\n",
+            "}",
+        ));
+        assert_eq!(rules.len(), 2);
+        let actions = apply_rules(&rules, &notification("x", "Build: failed", None));
+        assert!(actions.no_popup);
+        let actions = apply_rules(&rules, &notification("some app", "", None));
+        assert_eq!(actions.sound.as_deref(), Some("/tmp/a b.ogg"));
+    }
+
+    #[test]
+    fn rules_first_match_wins() {
+        let rules = rules(concat!(
+            "notifications {\n",
+            "    category \"im.received\" : urgency critical, sound /a.ogg\n",
+            "    app \"chat\" : urgency low, sound /b.ogg, mute\n",
+            "}",
+        ));
+        let actions = apply_rules(&rules, &notification("Chat", "hi", Some("im.received")));
+        assert_eq!(actions.urgency, Some(Urgency::Critical));
+        assert_eq!(actions.sound.as_deref(), Some("/a.ogg"));
+        assert!(actions.mute);
+
+        let actions = apply_rules(&rules, &notification("chat", "hi", None));
+        assert_eq!(actions.urgency, Some(Urgency::Low));
+        assert!(apply_rules(&rules, &notification("mail", "hi", None)).urgency.is_none());
+    }
+
+    #[test]
+    fn clock_rejects_malformed_times() {
+        assert_eq!(parse_clock("07:05"), Some(7 * 60 + 5));
+        assert_eq!(parse_clock(" 23:59 "), Some(23 * 60 + 59));
+        for bad in ["24:00", "12:60", "12", "12:", ":30", "-1:00", "ab:cd", "7.30", ""] {
+            assert_eq!(parse_clock(bad), None, "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn schedule_wraps_past_midnight() {
+        let night = "22:00-07:00";
+        assert!(in_schedule(night, 22 * 60));
+        assert!(in_schedule(night, 23 * 60 + 59));
+        assert!(in_schedule(night, 0));
+        assert!(in_schedule(night, 6 * 60 + 59));
+        assert!(!in_schedule(night, 7 * 60));
+        assert!(!in_schedule(night, 12 * 60));
+        assert!(!in_schedule(night, 21 * 60 + 59));
+    }
+
+    #[test]
+    fn schedule_skips_malformed_windows() {
+        let schedule = "25:00-07:00, nonsense, 12:30-13:00";
+        assert!(!in_schedule(schedule, 23 * 60));
+        assert!(in_schedule(schedule, 12 * 60 + 45));
+        assert!(!in_schedule(schedule, 13 * 60));
+        assert!(!in_schedule("", 0));
+        assert!(!in_schedule("08:00-08:00", 8 * 60));
+    }
+
+    #[test]
+    fn markup_closes_unbalanced_tags() {
+        assert_eq!(body_to_markup("<b>bold <i>both"), "<b>bold <i>both</i></b>");
+        assert_eq!(body_to_markup("<b>a<i>b</b>c</i>"), "<b>a<i>b</i></b>c");
+        assert_eq!(body_to_markup("stray</u>"), "stray");
+    }
+
+    #[test]
+    fn markup_drops_unsafe_links() {
+        assert_eq!(body_to_markup("<a href=\"javascript:alert(1)\">x</a>"), "x");
+        assert_eq!(
+            body_to_markup("<a href=\"https://example.org/?a=1&amp;b=2\">x</a>"),
+            "<a href=\"https://example.org/?a=1&amp;b=2\">x</a>",
+        );
+    }
+
+    #[test]
+    fn markup_drops_nested_links() {
+        assert_eq!(
+            body_to_markup("<a href=\"https://a\"><a href=\"https://b\">x</a></a>"),
+            "<a href=\"https://a\">x</a>",
+        );
+        assert_eq!(
+            body_to_markup("<a href=\"https://a\">x <b><a href=\"https://b\">y</b> z</a>"),
+            "<a href=\"https://a\">x <b>y</b> z</a>",
+        );
+    }
+
+    #[test]
+    fn markup_escapes_bare_characters() {
+        assert_eq!(body_to_markup("a & b < c > d"), "a &amp; b &lt; c &gt; d");
+        assert_eq!(body_to_markup("<3 &amp; &#65; &#x263A;"), "&lt;3 &amp; &#65; &#x263A;");
+    }
+
+    #[test]
+    fn markup_escapes_invalid_references() {
+        assert_eq!(body_to_markup("&#0;"), "&amp;#0;");
+        assert_eq!(body_to_markup("&#xD800;"), "&amp;#xD800;");
+        assert_eq!(body_to_markup("&#x110000;"), "&amp;#x110000;");
+        assert_eq!(body_to_markup("&bogus;"), "&amp;bogus;");
+    }
+
+    #[test]
+    fn markup_turns_br_into_newlines() {
+        assert_eq!(body_to_markup("one<br/>two<br>three"), "one\ntwo\nthree");
+    }
+}
@@ -1,21 +1,15 @@
-use libpulse_binding::{
-    callbacks::ListResult,
-    context::{
-        subscribe::{Facility, InterestMaskSet, Operation as SubOp},
-        Context, FlagSet as ContextFlagSet, State as ContextState,
-    },
-    volume::Volume,
-};
-use libpulse_glib_binding::Mainloop;
-use std::process::Command;
-
 use gtk4::glib;
 use gtk4::prelude::*;
+use gtk4_layer_shell::LayerShell;
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 use std::sync::mpsc as std_mpsc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+use crate::audio::{self, AudioState};
+use crate::backlight;
+use crate::notifications::probe_set_value;
 
 #[derive(Debug, Clone)]
 pub enum OsdEvent {
@@ -23,94 +17,197 @@ pub enum OsdEvent {
     Mute       { muted: bool, volume: u32 },
     MicMute    { muted: bool },
     MicInUse   { active: bool },
-    Brightness { percent: u32 },
-}
-
-#[derive(Default)]
-struct AudioState {
-    sink_volume: u32,
-    sink_muted:  bool,
-    src_muted:   bool,
-    src_running: bool,
+    Brightness { percent: u32, output: Option<String> },
+    MicGain    { volume: u32 },
+    KbdBacklight { percent: u32 },
+    CapsLock   { on: bool },
+    NumLock    { on: bool },
+    Target     { label: String },
 }
 
 fn adjust_volume(delta: f64) {
-    let arg = if delta < 0.0 { "+5%" } else { "-5%" };
-    let _ = Command::new("pactl")
-        .args(["set-sink-volume", "@DEFAULT_SINK@", arg])
-        .spawn();
+    audio::adjust_sink_volume(if delta < 0.0 { 5 } else { -5 });
 }
 
 fn adjust_brightness(delta: f64) {
-    let arg = if delta < 0.0 { "+5%" } else { "5%-" };
-    let _ = Command::new("brightnessctl")
-        .args(["set", arg])
-        .spawn();
+    backlight::adjust(None, if delta < 0.0 { 5 } else { -5 });
 }
 
-// ─── backlight ────────────────────────────────────────────────────────────────
+fn adjust_mic_gain(delta: f64) {
+    audio::adjust_source_volume(if delta < 0.0 { 5 } else { -5 });
+}
 
-fn find_backlight() -> Option<(std::path::PathBuf, u64)> {
-    let dir = std::fs::read_dir("/sys/class/backlight").ok()?;
-    for entry in dir.flatten() {
-        let base = entry.path();
-        let max_path = base.join("max_brightness");
-        let cur_path = base.join("brightness");
-        if cur_path.exists() && max_path.exists() {
-            let max: u64 = std::fs::read_to_string(&max_path)
-                .ok()?
-                .trim()
-                .parse()
-                .ok()?;
-            return Some((cur_path, max));
+fn adjust_backlight(device: &str, delta: f64) {
+    backlight::adjust(Some(device), if delta < 0.0 { 5 } else { -5 });
+}
+
+// ─── scroll pad targets ───────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq)]
+enum ScrollTarget {
+    Volume,
+    Brightness,
+    MicGain,
+    Backlight(String),
+}
+
+impl ScrollTarget {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "volume"            => Some(Self::Volume),
+            "brightness"        => Some(Self::Brightness),
+            "mic" | "mic_gain"  => Some(Self::MicGain),
+            other => other
+                .strip_prefix("backlight:")
+                .map(|dev| Self::Backlight(dev.trim().to_string())),
+        }
+    }
+
+    fn label(&self) -> String {
+        match self {
+            Self::Volume          => "Volume".to_string(),
+            Self::Brightness      => "Brightness".to_string(),
+            Self::MicGain         => "Mic gain".to_string(),
+            Self::Backlight(dev)  => format!("Brightness: {dev}"),
+        }
+    }
+
+    fn adjust(&self, delta: f64) {
+        match self {
+            Self::Volume         => adjust_volume(delta),
+            Self::Brightness     => adjust_brightness(delta),
+            Self::MicGain        => adjust_mic_gain(delta),
+            Self::Backlight(dev) => adjust_backlight(dev, delta),
         }
     }
-    None
 }
 
-fn read_brightness_percent(path: &std::path::Path, max: u64) -> Option<u32> {
-    let cur: u64 = std::fs::read_to_string(path)
-        .ok()?
-        .trim()
-        .parse()
-        .ok()?;
-    Some(((cur * 100 / max.max(1)) as u32).min(100))
+/// Every target Ctrl+scroll can cycle through, with one entry per backlight or DDC monitor.
+fn available_targets() -> Vec<ScrollTarget> {
+    let mut targets = vec![ScrollTarget::Volume, ScrollTarget::Brightness, ScrollTarget::MicGain];
+    let outputs = backlight::outputs();
+    if outputs.len() > 1 {
+        targets.extend(outputs.into_iter().map(ScrollTarget::Backlight));
+    }
+    targets
+}
+
+/// Zones from `scroll_pad: volume, brightness` in the probe, left to right.
+fn scroll_zones() -> Vec<ScrollTarget> {
+    let zones: Vec<ScrollTarget> = probe_set_value("scroll_pad")
+        .map(|v| v.split(',').filter_map(ScrollTarget::parse).collect())
+        .unwrap_or_default();
+    if zones.is_empty() { vec![ScrollTarget::Volume] } else { zones }
+}
+
+// ─── keyboard backlight and lock LEDs ─────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum LedKind {
+    KbdBacklight,
+    CapsLock,
+    NumLock,
+}
+
+struct Led {
+    kind: LedKind,
+    path: std::path::PathBuf,
+    max:  u64,
+}
+
+impl Led {
+    fn read(&self) -> Option<u64> {
+        std::fs::read_to_string(&self.path).ok()?.trim().parse().ok()
+    }
+
+    fn event(&self, value: u64) -> OsdEvent {
+        match self.kind {
+            LedKind::KbdBacklight => OsdEvent::KbdBacklight {
+                percent: ((value * 100 / self.max.max(1)) as u32).min(100),
+            },
+            LedKind::CapsLock => OsdEvent::CapsLock { on: value > 0 },
+            LedKind::NumLock  => OsdEvent::NumLock  { on: value > 0 },
+        }
+    }
+}
+
+fn find_leds() -> Vec<Led> {
+    let Ok(dir) = std::fs::read_dir("/sys/class/leds") else { return Vec::new() };
+    let mut leds = Vec::new();
+    for entry in dir.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let kind = if name.contains("kbd_backlight") {
+            LedKind::KbdBacklight
+        } else if name.ends_with("::capslock") {
+            LedKind::CapsLock
+        } else if name.ends_with("::numlock") {
+            LedKind::NumLock
+        } else {
+            continue;
+        };
+        let base = entry.path();
+        let path = base.join("brightness");
+        if !path.exists() {
+            continue;
+        }
+        let max = std::fs::read_to_string(base.join("max_brightness"))
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(1);
+        leds.push(Led { kind, path, max });
+    }
+    leds
 }
 
-fn spawn_brightness_watcher() -> Option<std_mpsc::Receiver<u32>> {
-    let (path, max) = find_backlight()?;
-    let (tx, rx) = std_mpsc::channel::<u32>();
+fn spawn_led_watcher() -> Option<std_mpsc::Receiver<OsdEvent>> {
+    let leds = find_leds();
+    if leds.is_empty() {
+        return None;
+    }
+    let (tx, rx) = std_mpsc::channel::<OsdEvent>();
 
     std::thread::spawn(move || {
         use inotify::{Inotify, WatchMask};
+        use std::collections::HashMap;
 
         let mut inotify = match Inotify::init() {
             Ok(i) => i,
             Err(e) => { eprintln!("[osd] inotify init failed: {e}"); return; }
         };
 
-        if let Err(e) = inotify.watches().add(&path, WatchMask::CLOSE_WRITE | WatchMask::MODIFY) {
-            eprintln!("[osd] inotify watch failed on {}: {e}", path.display());
-            return;
+        let mut watches = HashMap::new();
+        for (i, led) in leds.iter().enumerate() {
+            match inotify.watches().add(&led.path, WatchMask::CLOSE_WRITE | WatchMask::MODIFY) {
+                Ok(wd) => { watches.insert(wd, i); }
+                Err(e) => eprintln!("[osd] inotify watch failed on {}: {e}", led.path.display()),
+            }
+        }
+
+        // Every keyboard carries its own lock LEDs, so track state per kind to
+        // report a toggle once rather than once per device.
+        let mut last: HashMap<LedKind, u64> = HashMap::new();
+        for led in &leds {
+            if let Some(v) = led.read() {
+                last.insert(led.kind, v);
+            }
         }
 
-        let mut last: Option<u32> = None;
-        let mut buf = [0u8; 512];
+        let mut buf = [0u8; 1024];
 
         loop {
-            match inotify.read_events_blocking(&mut buf) {
-                Ok(_) => {
-                    if let Some(pct) = read_brightness_percent(&path, max) {
-                        if last != Some(pct) {
-                            last = Some(pct);
-                            let _ = tx.send(pct);
-                        }
-                    }
-                }
+            let events = match inotify.read_events_blocking(&mut buf) {
+                Ok(events) => events,
                 Err(e) => {
                     eprintln!("[osd] inotify read error: {e}");
                     break;
                 }
+            };
+            for event in events {
+                let Some(led) = watches.get(&event.wd).map(|&i| &leds[i]) else { continue };
+                let Some(value) = led.read() else { continue };
+                if last.insert(led.kind, value) != Some(value) && tx.send(led.event(value)).is_err() {
+                    return;
+                }
             }
         }
     });
@@ -118,36 +215,54 @@ fn spawn_brightness_watcher() -> Option<std_mpsc::Receiver<u32>> {
     Some(rx)
 }
 
+fn connect_led_events(rx: std_mpsc::Receiver<OsdEvent>, emit: Rc<dyn Fn(OsdEvent)>) {
+    glib::timeout_add_local(Duration::from_millis(50), move || {
+        let mut latest = None;
+        loop {
+            match rx.try_recv() {
+                Ok(event) => { latest = Some(event); }
+                Err(std_mpsc::TryRecvError::Empty) => break,
+                Err(std_mpsc::TryRecvError::Disconnected) => {
+                    return glib::ControlFlow::Break;
+                }
+            }
+        }
+        if let Some(event) = latest {
+            emit(event);
+        }
+        glib::ControlFlow::Continue
+    });
+}
+
 // ─── brightness connector ─────────────────────────────────────────────────────
 
+/// Shows brightness changes on the monitor whose output changed, labelled with
+/// the connector when there is more than one output to tell apart.
 fn connect_brightness(
-    rx:       std_mpsc::Receiver<u32>,
-    osd_box:  gtk4::Box,
-    revealer: gtk4::Revealer,
-    capsule:  gtk4::Box,
-    window:   gtk4::ApplicationWindow,
-    hide_id:  Rc<RefCell<Option<glib::SourceId>>>,
-    osd_label: gtk4::Label,
+    rx:      std_mpsc::Receiver<backlight::Change>,
+    show_on: Rc<dyn Fn(OsdEvent, Option<gtk4::gdk::Monitor>)>,
 ) {
-    let rx = Rc::new(RefCell::new(rx));
+    let labelled = backlight::outputs().len() > 1;
 
     glib::timeout_add_local(Duration::from_millis(50), move || {
-        let mut last_pct = None;
+        let mut latest = None;
         loop {
-            match rx.borrow().try_recv() {
-                Ok(pct) => { last_pct = Some(pct); }
+            match rx.try_recv() {
+                Ok(change) => { latest = Some(change); }
                 Err(std_mpsc::TryRecvError::Empty) => break,
                 Err(std_mpsc::TryRecvError::Disconnected) => {
                     return glib::ControlFlow::Break;
                 }
             }
         }
-        if let Some(pct) = last_pct {
-            show_osd(
-                &osd_box, &revealer, &capsule, &window,
-                &hide_id, OsdEvent::Brightness { percent: pct },
-                &osd_label,
-            );
+        if let Some(change) = latest {
+            let monitor = change
+                .connector
+                .as_deref()
+                .zip(gtk4::gdk::Display::default())
+                .and_then(|(c, display)| crate::resolve_monitor(&display, c));
+            let output = change.connector.filter(|_| labelled);
+            show_on(OsdEvent::Brightness { percent: change.percent, output }, monitor);
         }
         glib::ControlFlow::Continue
     });
@@ -162,8 +277,6 @@ pub fn connect_osd_to_dock(
     window:       &gtk4::ApplicationWindow,
     osd_label:    &gtk4::Label,
 ) {
-    let is_volume_mode = true;
-
     let scrl_pad = gtk4::Box::builder()
         .orientation(gtk4::Orientation::Vertical)
         .hexpand(true)
@@ -175,220 +288,155 @@ pub fn connect_osd_to_dock(
         .css_classes(["scrollPad"])
         .build();
 
-    let scroll_controller = gtk4::EventControllerScroll::new(gtk4::EventControllerScrollFlags::VERTICAL);
-
-    scroll_controller.connect_scroll(move |_, _dx, dy| {
-        if is_volume_mode {
-            adjust_volume(dy);
-        } else {
-            adjust_brightness(dy);
-        }
-        glib::Propagation::Proceed
-    });
-
-    scrl_pad.add_controller(scroll_controller);
-
-    capsule.append(&scrl_pad);
-
-    if let Some(bright_rx) = spawn_brightness_watcher() {
-        connect_brightness(
-            bright_rx,
-            osd_box.clone(),
-            osd_revealer.clone(),
-            capsule.clone(),
-            window.clone(),
-            Rc::new(RefCell::new(None)),
-            osd_label.clone(),
-        );
-    }
+    let hide_id: Rc<RefCell<Option<glib::SourceId>>> = Default::default();
 
-    let mainloop = Rc::new(RefCell::new(
-        Mainloop::new(None).expect("PA glib mainloop"),
-    ));
+    let zones:       Rc<RefCell<Vec<ScrollTarget>>> = Rc::new(RefCell::new(scroll_zones()));
+    let pointer_x:   Rc<Cell<f64>>                  = Rc::new(Cell::new(0.0));
+    let active_zone: Rc<Cell<Option<usize>>>        = Rc::new(Cell::new(None));
+    let last_cycle:  Rc<Cell<Option<Instant>>>      = Rc::new(Cell::new(None));
 
-    let context = {
-        let ml = mainloop.borrow();
-        Rc::new(RefCell::new(
-            Context::new(&*ml, "capsule-osd").expect("PA context"),
-        ))
+    let zone_at = {
+        let zones = Rc::clone(&zones);
+        let pad   = scrl_pad.clone();
+        move |x: f64| -> usize {
+            let count = zones.borrow().len().max(1);
+            let width = pad.width().max(1) as f64;
+            ((x / width * count as f64).floor().max(0.0) as usize).min(count - 1)
+        }
     };
 
-    let state:         Rc<RefCell<AudioState>>             = Default::default();
-    let audio_hide_id: Rc<RefCell<Option<glib::SourceId>>> = Default::default();
-
-    {
-        let ctx = Rc::clone(&context);
-        let st  = Rc::clone(&state);
-        let bx  = osd_box.clone();
-        let rev = osd_revealer.clone();
-        let cap = capsule.clone();
-        let win = window.clone();
-        let hid = Rc::clone(&audio_hide_id);
+    // Brightness OSDs follow the monitor being changed; everything else goes
+    // back to wherever the window started out.
+    let home_monitor = window.monitor();
+    let show_on: Rc<dyn Fn(OsdEvent, Option<gtk4::gdk::Monitor>)> = {
+        let bx     = osd_box.clone();
+        let rev    = osd_revealer.clone();
+        let cap    = capsule.clone();
+        let win    = window.clone();
+        let hid    = Rc::clone(&hide_id);
         let osxout = osd_label.clone();
-
-        context.borrow_mut().set_state_callback(Some(Box::new(move || {
-            let cs = unsafe { (*ctx.as_ptr()).get_state() };
-            match cs {
-                ContextState::Ready => {
-                    let ctx2 = Rc::clone(&ctx);
-                    let st2  = Rc::clone(&st);
-                    let bx2  = bx.clone();
-                    let rev2 = rev.clone();
-                    let cap2 = cap.clone();
-                    let win2 = win.clone();
-                    let hid2 = Rc::clone(&hid);
-                    let osxout2 = osxout.clone();
-                    glib::idle_add_local_once(move || {
-                        on_context_ready(&ctx2, &st2, &bx2, &rev2, &cap2, &win2, &hid2, &osxout2);
-                    });
-                }
-                ContextState::Failed | ContextState::Terminated => {
-                    eprintln!("[osd] PA context failed/terminated");
-                }
-                _ => {}
+        Rc::new(move |event, monitor| {
+            let target = monitor.or_else(|| home_monitor.clone());
+            if win.monitor() != target {
+                win.set_monitor(target.as_ref());
             }
-        })));
+            show_osd(&bx, &rev, &cap, &win, &hid, event, &osxout);
+        })
+    };
+    let emit: Rc<dyn Fn(OsdEvent)> = {
+        let show_on = Rc::clone(&show_on);
+        Rc::new(move |event| show_on(event, None))
+    };
+
+    let motion = gtk4::EventControllerMotion::new();
+    {
+        let px = Rc::clone(&pointer_x);
+        motion.connect_enter(move |_, x, _| px.set(x));
     }
+    {
+        let px = Rc::clone(&pointer_x);
+        motion.connect_motion(move |_, x, _| px.set(x));
+    }
+    {
+        let active = Rc::clone(&active_zone);
+        motion.connect_leave(move |_| active.set(None));
+    }
+    scrl_pad.add_controller(motion);
 
-    context
-        .borrow_mut()
-        .connect(None, ContextFlagSet::NOFLAGS, None)
-        .expect("PA connect");
+    let scroll_controller = gtk4::EventControllerScroll::new(gtk4::EventControllerScrollFlags::VERTICAL);
 
-    std::mem::forget((mainloop, context));
-}
+    let scroll_emit = Rc::clone(&emit);
+    scroll_controller.connect_scroll(move |ctrl, _dx, dy| {
+        let zone = zone_at(pointer_x.get());
 
-// ─── context ready ────────────────────────────────────────────────────────────
+        if ctrl.current_event_state().contains(gtk4::gdk::ModifierType::CONTROL_MASK) {
+            // Smooth scrolling fires many events per gesture; cycle at most once per 250 ms.
+            let now = Instant::now();
+            if last_cycle.get().is_some_and(|t| now.duration_since(t) < Duration::from_millis(250)) {
+                return glib::Propagation::Stop;
+            }
+            last_cycle.set(Some(now));
+
+            let targets = available_targets();
+            let mut zones = zones.borrow_mut();
+            let Some(current) = zones.get(zone).cloned() else { return glib::Propagation::Stop };
+            let pos  = targets.iter().position(|t| *t == current).unwrap_or(0);
+            let next = if dy < 0.0 {
+                (pos + targets.len() - 1) % targets.len()
+            } else {
+                (pos + 1) % targets.len()
+            };
+            zones[zone] = targets[next].clone();
+            active_zone.set(Some(zone));
+            scroll_emit(OsdEvent::Target { label: zones[zone].label() });
+            return glib::Propagation::Stop;
+        }
 
-fn on_context_ready(
-    ctx:      &Rc<RefCell<Context>>,
-    state:    &Rc<RefCell<AudioState>>,
-    osd_box:  &gtk4::Box,
-    revealer: &gtk4::Revealer,
-    capsule:  &gtk4::Box,
-    window:   &gtk4::ApplicationWindow,
-    hide_id:  &Rc<RefCell<Option<glib::SourceId>>>,
-    osd_label: &gtk4::Label,
-) {
-    fetch_sink_info(ctx, state, osd_box, revealer, capsule, window, hide_id, false, osd_label);
-    fetch_source_info(ctx, state, osd_box, revealer, capsule, window, hide_id, false, osd_label);
+        let Some(target) = zones.borrow().get(zone).cloned() else {
+            return glib::Propagation::Proceed;
+        };
 
-    ctx.borrow_mut()
-        .subscribe(InterestMaskSet::SINK | InterestMaskSet::SOURCE, |_| {});
+        if active_zone.replace(Some(zone)) != Some(zone) && zones.borrow().len() > 1 {
+            scroll_emit(OsdEvent::Target { label: target.label() });
+        }
 
-    {
-        let ctx2 = Rc::clone(ctx);
-        let st2  = Rc::clone(state);
-        let bx2  = osd_box.clone();
-        let rev2 = revealer.clone();
-        let cap2 = capsule.clone();
-        let win2 = window.clone();
-        let hid2 = Rc::clone(hide_id);
-        let osxout2= osd_label.clone();
-
-        ctx.borrow_mut().set_subscribe_callback(Some(Box::new(
-            move |facility, op, _index| {
-                match (facility, op) {
-                    (Some(Facility::Sink), Some(SubOp::Changed)) => {
-                        fetch_sink_info(&ctx2, &st2, &bx2, &rev2, &cap2, &win2, &hid2, true, &osxout2);
-                    }
-                    (Some(Facility::Source), Some(SubOp::Changed)) => {
-                        fetch_source_info(&ctx2, &st2, &bx2, &rev2, &cap2, &win2, &hid2, true, &osxout2);
-                    }
-                    _ => {}
-                }
-            },
-        )));
-    }
-}
+        target.adjust(dy);
 
-// ─── sink / source fetchers ───────────────────────────────────────────────────
+        glib::Propagation::Proceed
+    });
 
-fn fetch_sink_info(
-    ctx:      &Rc<RefCell<Context>>,
-    state:    &Rc<RefCell<AudioState>>,
-    osd_box:  &gtk4::Box,
-    revealer: &gtk4::Revealer,
-    capsule:  &gtk4::Box,
-    window:   &gtk4::ApplicationWindow,
-    hide_id:  &Rc<RefCell<Option<glib::SourceId>>>,
-    emit:     bool,
-    osd_label: &gtk4::Label,
-) {
-    let st  = Rc::clone(state);
-    let bx  = osd_box.clone();
-    let rev = revealer.clone();
-    let cap = capsule.clone();
-    let win = window.clone();
-    let hid = Rc::clone(hide_id);
-    let osxout = osd_label.clone();
+    scrl_pad.add_controller(scroll_controller);
 
-    let introspector = ctx.borrow().introspect();
-    let _ = introspector.get_sink_info_by_name("@DEFAULT_SINK@", move |res| {
-        let ListResult::Item(info) = res else { return };
+    capsule.append(&scrl_pad);
 
-        let vol   = pa_vol_to_percent(info.volume.avg());
-        let muted = info.mute;
+    if let Some(bright_rx) = backlight::watch() {
+        connect_brightness(bright_rx, show_on);
+    }
 
-        let mut s = st.borrow_mut();
-        let vol_changed  = s.sink_volume != vol;
-        let mute_changed = s.sink_muted  != muted;
-        s.sink_volume = vol;
-        s.sink_muted  = muted;
-        drop(s);
+    if let Some(led_rx) = spawn_led_watcher() {
+        connect_led_events(led_rx, Rc::clone(&emit));
+    }
+
+    let last: RefCell<Option<AudioState>> = RefCell::new(None);
 
-        if !emit { return; }
-        if mute_changed {
-            show_osd(&bx, &rev, &cap, &win, &hid, OsdEvent::Mute { muted, volume: vol }, &osxout);
-        } else if vol_changed {
-            show_osd(&bx, &rev, &cap, &win, &hid, OsdEvent::Volume { volume: vol, muted }, &osxout);
+    audio::subscribe(move |state| {
+        let prev = last.replace(Some(state.clone()));
+        let Some(prev) = prev else { return };
+        if let Some(event) = audio_osd_event(&prev, state) {
+            emit(event);
         }
     });
 }
 
-fn fetch_source_info(
-    ctx:      &Rc<RefCell<Context>>,
-    state:    &Rc<RefCell<AudioState>>,
-    osd_box:  &gtk4::Box,
-    revealer: &gtk4::Revealer,
-    capsule:  &gtk4::Box,
-    window:   &gtk4::ApplicationWindow,
-    hide_id:  &Rc<RefCell<Option<glib::SourceId>>>,
-    emit:     bool,
-    osd_label: &gtk4::Label,
-) {
-    let st  = Rc::clone(state);
-    let bx  = osd_box.clone();
-    let rev = revealer.clone();
-    let cap = capsule.clone();
-    let win = window.clone();
-    let hid = Rc::clone(hide_id);
-    let osxout = osd_label.clone();
-
-    let introspector = ctx.borrow().introspect();
-    let _ = introspector.get_source_info_by_name("@DEFAULT_SOURCE@", move |res| {
-        let ListResult::Item(info) = res else { return };
-
-        let muted   = info.mute;
-        let running = matches!(
-            info.state,
-            libpulse_binding::def::SourceState::Running
-        );
-
-        let mut s = st.borrow_mut();
-        let mute_changed    = s.src_muted   != muted;
-        let running_changed = s.src_running != running;
-        s.src_muted   = muted;
-        s.src_running = running;
-        drop(s);
-
-        if !emit { return; }
-        if mute_changed {
-            show_osd(&bx, &rev, &cap, &win, &hid, OsdEvent::MicMute { muted }, &osxout);
-        } else if running_changed && running {
-            show_osd(&bx, &rev, &cap, &win, &hid, OsdEvent::MicInUse { active: true }, &osxout);
+// ─── audio events ─────────────────────────────────────────────────────────────
+
+fn audio_osd_event(prev: &AudioState, state: &AudioState) -> Option<OsdEvent> {
+    if let (Some(old), Some(new)) = (prev.default_sink(), state.default_sink())
+        && old.name == new.name
+    {
+        if old.muted != new.muted {
+            return Some(OsdEvent::Mute { muted: new.muted, volume: new.volume });
         }
-    });
+        if old.volume != new.volume {
+            return Some(OsdEvent::Volume { volume: new.volume, muted: new.muted });
+        }
+    }
+
+    if let (Some(old), Some(new)) = (prev.default_source(), state.default_source())
+        && old.name == new.name
+    {
+        if old.muted != new.muted {
+            return Some(OsdEvent::MicMute { muted: new.muted });
+        }
+        if old.volume != new.volume {
+            return Some(OsdEvent::MicGain { volume: new.volume });
+        }
+        if !old.running && new.running {
+            return Some(OsdEvent::MicInUse { active: true });
+        }
+    }
+
+    None
 }
 
 // ─── show / apply ─────────────────────────────────────────────────────────────
@@ -452,7 +500,7 @@ fn show_osd(
 fn apply_osd_event(osd_box: &gtk4::Box, event: &OsdEvent, osd_label: &gtk4::Label) {
     let total_width = 300;
 
-    for cls in &["osd-volume", "osd-muted", "osd-mic", "osd-mic-active", "osd-brightness"] {
+    for cls in &["osd-volume", "osd-muted", "osd-mic", "osd-mic-active", "osd-brightness", "osd-target", "osd-kbd", "osd-lock"] {
         osd_box.remove_css_class(cls);
     }
 
@@ -499,18 +547,41 @@ fn apply_osd_event(osd_box: &gtk4::Box, event: &OsdEvent, osd_label: &gtk4::Labe
             osd_box.add_css_class("osd-mic");
             osd_label.set_text(&format!("Mic not in use"));
         }
-        OsdEvent::Brightness { percent } => {
+        OsdEvent::Brightness { percent, output } => {
             let fill = ((total_width as f64) * (*percent as f64 / 100.0)) as i32;
             osd_box.set_width_request(fill.max(4));
             osd_box.add_css_class("osd-brightness");
-            osd_label.set_text(&format!("{}", percent));
+            match output {
+                Some(output) => osd_label.set_text(&format!("{}: {}", output, percent)),
+                None         => osd_label.set_text(&format!("{}", percent)),
+            }
+        }
+        OsdEvent::MicGain { volume } => {
+            let fill = ((total_width as f64) * (*volume as f64 / 100.0)) as i32;
+            osd_box.set_width_request(fill.max(4));
+            osd_box.add_css_class("osd-mic");
+            osd_label.set_text(&format!("Mic: {}", volume));
+        }
+        OsdEvent::KbdBacklight { percent } => {
+            let fill = ((total_width as f64) * (*percent as f64 / 100.0)) as i32;
+            osd_box.set_width_request(fill.max(4));
+            osd_box.add_css_class("osd-kbd");
+            osd_label.set_text(&format!("Keyboard: {}", percent));
+        }
+        OsdEvent::CapsLock { on } => {
+            osd_box.set_width_request(if *on { total_width } else { 4 });
+            osd_box.add_css_class("osd-lock");
+            osd_label.set_text(if *on { "Caps Lock on" } else { "Caps Lock off" });
+        }
+        OsdEvent::NumLock { on } => {
+            osd_box.set_width_request(if *on { total_width } else { 4 });
+            osd_box.add_css_class("osd-lock");
+            osd_label.set_text(if *on { "Num Lock on" } else { "Num Lock off" });
+        }
+        OsdEvent::Target { label } => {
+            osd_box.set_width_request(total_width);
+            osd_box.add_css_class("osd-target");
+            osd_label.set_text(&format!("Scroll: {}", label));
         }
     }
 }
-
-// ─── helpers ──────────────────────────────────────────────────────────────────
-
-fn pa_vol_to_percent(v: Volume) -> u32 {
-    let norm = Volume::NORMAL.0 as f64;
-    ((v.0 as f64 / norm) * 100.0).round().clamp(0.0, 150.0) as u32
-}
\ No newline at end of file